@@ -0,0 +1,239 @@
+pub mod activity;
+pub mod ai_pause;
+pub mod ai_resume;
+pub mod backfill;
+pub mod clearwarnings;
+pub mod compare;
+pub mod config;
+pub mod db_restart;
+pub mod dbstats;
+pub mod debug_check;
+pub mod dump_payload;
+pub mod errors;
+pub mod inject;
+pub mod inspect;
+pub mod latency;
+pub mod load_profile;
+pub mod loadtest;
+pub mod moderate;
+pub mod parse_stats;
+pub mod pending;
+pub mod ping_ai;
+pub mod preview_log;
+pub mod profile;
+pub mod recheck;
+pub mod remove;
+pub mod replay;
+pub mod rotatekey;
+pub mod save_profile;
+pub mod schedule_clear;
+pub mod setcontext;
+pub mod setlogchannel;
+pub mod setmodel;
+pub mod shadow_report;
+pub mod strictness;
+pub mod toggle;
+pub mod usage;
+pub mod warnings;
+
+use serenity::all::{Channel, ChannelId, CommandInteraction, Context, CreateCommand, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, EditInteractionResponse, GuildId, MessageId, UserId};
+use tracing::warn;
+use crate::{config as bot_config, Handler};
+
+/// Leaves room for formatting within Discord's 2000 character message limit.
+pub const MAX_REPLY_LEN: usize = 1900;
+
+pub fn all() -> Vec<CreateCommand> {
+    let mut commands = vec![
+        activity::register(),
+        ai_pause::register(),
+        ai_resume::register(),
+        backfill::register(),
+        clearwarnings::register(),
+        compare::register(),
+        config::register(),
+        db_restart::register(),
+        dbstats::register(),
+        errors::register(),
+        inspect::register(),
+        latency::register(),
+        load_profile::register(),
+        moderate::register(),
+        parse_stats::register(),
+        pending::register(),
+        ping_ai::register(),
+        preview_log::register(),
+        profile::register(),
+        recheck::register(),
+        remove::register(),
+        replay::register(),
+        rotatekey::register(),
+        save_profile::register(),
+        schedule_clear::register(),
+        setcontext::register(),
+        setlogchannel::register(),
+        setmodel::register(),
+        shadow_report::register(),
+        strictness::register(),
+        toggle::register(),
+        usage::register(),
+        warnings::register()
+    ];
+    if bot_config::get().enable_loadtest {
+        commands.push(loadtest::register());
+    }
+    if bot_config::get().enable_debug_commands {
+        commands.push(debug_check::register());
+        commands.push(dump_payload::register());
+        commands.push(inject::register());
+    }
+    commands
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    match command.data.name.as_str() {
+        moderate::NAME => moderate::run(handler, ctx, command).await,
+        "activity" => activity::run(handler, ctx, command).await,
+        "ai-pause" => ai_pause::run(handler, ctx, command).await,
+        "ai-resume" => ai_resume::run(handler, ctx, command).await,
+        "backfill" => backfill::run(handler, ctx, command).await,
+        "clearwarnings" => clearwarnings::run(handler, ctx, command).await,
+        "compare" => compare::run(handler, ctx, command).await,
+        "config" => config::run(ctx, command).await,
+        "db-restart" => db_restart::run(handler, ctx, command).await,
+        "dbstats" => dbstats::run(handler, ctx, command).await,
+        "debug-check" => debug_check::run(handler, ctx, command).await,
+        "dump-payload" => dump_payload::run(handler, ctx, command).await,
+        "errors" => errors::run(handler, ctx, command).await,
+        "inject" => inject::run(handler, ctx, command).await,
+        "inspect" => inspect::run(handler, ctx, command).await,
+        "latency" => latency::run(ctx, command).await,
+        "load-profile" => load_profile::run(handler, ctx, command).await,
+        "loadtest" => loadtest::run(handler, ctx, command).await,
+        "parse-stats" => parse_stats::run(handler, ctx, command).await,
+        "pending" => pending::run(handler, ctx, command).await,
+        "ping-ai" => ping_ai::run(handler, ctx, command).await,
+        "preview-log" => preview_log::run(ctx, command).await,
+        "profile" => profile::run(handler, ctx, command).await,
+        "recheck" => recheck::run(handler, ctx, command).await,
+        "remove" => remove::run(handler, ctx, command).await,
+        "replay" => replay::run(handler, ctx, command).await,
+        "rotatekey" => rotatekey::run(handler, ctx, command).await,
+        "save-profile" => save_profile::run(handler, ctx, command).await,
+        "schedule-clear" => schedule_clear::run(handler, ctx, command).await,
+        "setcontext" => setcontext::run(handler, ctx, command).await,
+        "setlogchannel" => setlogchannel::run(handler, ctx, command).await,
+        "setmodel" => setmodel::run(handler, ctx, command).await,
+        "shadow-report" => shadow_report::run(handler, ctx, command).await,
+        "strictness" => strictness::run(handler, ctx, command).await,
+        "toggle" => toggle::run(handler, ctx, command).await,
+        "usage" => usage::run(handler, ctx, command).await,
+        "warnings" => warnings::run(handler, ctx, command).await,
+        name => warn!("received unknown command {name}")
+    }
+}
+
+pub async fn reply(ctx: &Context, command: &CommandInteraction, content: impl Into<String>) {
+    let message = CreateInteractionResponseMessage::new()
+        .content(content)
+        .ephemeral(true);
+    if let Err(why) = command.create_response(&ctx.http, CreateInteractionResponse::Message(message)).await {
+        warn!("failed to respond to /{}: {why}", command.data.name);
+    }
+}
+
+/// Sends another ephemeral message after the reply, for output that does not fit in one.
+pub async fn follow_up(ctx: &Context, command: &CommandInteraction, content: impl Into<String>) {
+    let message = CreateInteractionResponseFollowup::new()
+        .content(content)
+        .ephemeral(true);
+    if let Err(why) = command.create_followup(&ctx.http, message).await {
+        warn!("failed to follow up on /{}: {why}", command.data.name);
+    }
+}
+
+/// Replies with a refusal and returns `false` unless the invoker is listed in `OWNER_IDS`.
+pub async fn require_owner(ctx: &Context, command: &CommandInteraction) -> bool {
+    if bot_config::get().owner_ids.contains(&command.user.id.get()) {
+        return true;
+    }
+    reply(ctx, command, "This command is restricted to the bot owner.").await;
+    false
+}
+
+pub async fn defer(ctx: &Context, command: &CommandInteraction) {
+    if let Err(why) = command.defer_ephemeral(&ctx.http).await {
+        warn!("failed to defer /{}: {why}", command.data.name);
+    }
+}
+
+pub async fn edit(ctx: &Context, command: &CommandInteraction, content: impl Into<String>) {
+    if let Err(why) = command.edit_response(&ctx.http, EditInteractionResponse::new().content(content)).await {
+        warn!("failed to edit response to /{}: {why}", command.data.name);
+    }
+}
+
+pub fn string_option<'a>(command: &'a CommandInteraction, name: &str) -> Option<&'a str> {
+    command.data.options.iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_str())
+}
+
+pub fn channel_option(command: &CommandInteraction, name: &str) -> Option<ChannelId> {
+    command.data.options.iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_channel_id())
+}
+
+pub fn user_option(command: &CommandInteraction, name: &str) -> Option<UserId> {
+    command.data.options.iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_user_id())
+}
+
+/// Parses a `https://discord.com/channels/<guild>/<channel>/<message>` link into its guild,
+/// `None` for DM links, channel and message.
+pub fn parse_message_link(link: &str) -> Option<(Option<GuildId>, ChannelId, MessageId)> {
+    let path = link.trim().split_once("/channels/")?.1;
+    let mut ids = path.split('/').map(|x| x.parse::<u64>().ok().filter(|&x| x != 0));
+    let (guild_id, channel_id, message_id) = (ids.next()?, ids.next()??, ids.next()??);
+    Some((guild_id.map(GuildId::new), ChannelId::new(channel_id), MessageId::new(message_id)))
+}
+
+/// The channel and message `link` points to, if both are in the guild `command` was used in.
+/// The link's guild is checked first, then the channel is resolved, since links can be edited
+/// to pair one guild's id with another guild's channel. Replies with why on failure.
+pub async fn guild_message_link(ctx: &Context, command: &CommandInteraction, link: &str) -> Option<(GuildId, ChannelId, MessageId)> {
+    let Some((guild_id, channel_id, message_id)) = parse_message_link(link) else {
+        edit(ctx, command, "That is not a valid message link.").await;
+        return None;
+    };
+    let Some(guild_id) = guild_id.filter(|x| Some(*x) == command.guild_id) else {
+        edit(ctx, command, "That message is not in this server.").await;
+        return None;
+    };
+    match channel_id.to_channel(&ctx.http).await {
+        Ok(Channel::Guild(channel)) if channel.guild_id == guild_id => Some((guild_id, channel_id, message_id)),
+        Ok(_) => {
+            edit(ctx, command, "That message is not in this server.").await;
+            None
+        },
+        Err(why) => {
+            warn!("failed to resolve channel {channel_id}: {why}");
+            edit(ctx, command, "Could not fetch that message.").await;
+            None
+        }
+    }
+}
+
+pub fn boolean_option(command: &CommandInteraction, name: &str) -> Option<bool> {
+    command.data.options.iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_bool())
+}
+
+pub fn integer_option(command: &CommandInteraction, name: &str) -> Option<i64> {
+    command.data.options.iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_i64())
+}
@@ -0,0 +1,33 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions, Timestamp};
+use crate::{config, truncate, Handler};
+use super::{reply, require_owner, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("activity")
+        .description("Show when each channel last had a message, to spot dead channels")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let mut activity = handler.activity();
+    if activity.is_empty() {
+        reply(ctx, command, "No messages have been seen since the database started.").await;
+        return;
+    }
+    activity.sort_by_key(|(_, timestamp)| std::cmp::Reverse(*timestamp));
+    let now = Timestamp::now().unix_timestamp();
+    // Channels quiet for longer than the TTL have nothing left stored to moderate against.
+    let ttl = config::get().context_ttl_secs;
+    let lines = activity.iter()
+        .map(|(channel_id, timestamp)| {
+            let expired = if ttl.is_some_and(|x| now - timestamp > x) { " (past CONTEXT_TTL_SECS)" } else { "" };
+            format!("<#{channel_id}>: <t:{timestamp}:R>{expired}")
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let content = format!("Last message per channel since the database started, newest first:\n{lines}");
+    reply(ctx, command, truncate(&content, MAX_REPLY_LEN)).await;
+}
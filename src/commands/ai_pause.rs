@@ -0,0 +1,24 @@
+use std::sync::atomic::Ordering;
+
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use tracing::info;
+use crate::Handler;
+use super::{reply, require_owner};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("ai-pause")
+        .description("Stop sending messages to the model while still storing them as context")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    if handler.ai_paused.swap(true, Ordering::Relaxed) {
+        reply(ctx, command, "AI calls are already paused.").await;
+        return;
+    }
+    info!("{} paused AI calls", command.user.id);
+    reply(ctx, command, "Paused AI calls. Messages are still stored and checked with heuristics until `/ai-resume`.").await;
+}
@@ -0,0 +1,41 @@
+use std::sync::atomic::Ordering;
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{Handler, ValidationStatus, PROMPT_CONTEXT};
+use super::{boolean_option, defer, edit, reply, require_owner};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("ai-resume")
+        .description("Resume sending messages to the model after /ai-pause")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Boolean, "recheck", "Validate every channel with messages stored while paused")
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    if !handler.ai_paused.swap(false, Ordering::Relaxed) {
+        reply(ctx, command, "AI calls are not paused.").await;
+        return;
+    }
+    info!("{} resumed AI calls", command.user.id);
+    if !boolean_option(command, "recheck").unwrap_or(false) {
+        reply(ctx, command, "Resumed AI calls.").await;
+        return;
+    }
+    defer(ctx, command).await;
+    // One request per channel covers its backlog, since a verdict marks the whole context as reviewed.
+    let mut rechecked = 0;
+    for channel in handler.stats().channels {
+        if handler.get_by_status(channel.channel_id, ValidationStatus::NotValidated).is_empty() {
+            continue;
+        }
+        handler.ai_request(ctx, handler.get_latest(channel.channel_id, PROMPT_CONTEXT)).await;
+        rechecked += 1;
+    }
+    edit(ctx, command, format!("Resumed AI calls and rechecked {rechecked} channels with a backlog.")).await;
+}
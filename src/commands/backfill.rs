@@ -0,0 +1,52 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GetMessages, Permissions};
+use tracing::{info, warn};
+use crate::{database::definitions::DatabaseMessage, Handler, PartialMessage, ValidationStatus};
+use super::{channel_option, defer, edit, integer_option, require_owner};
+
+/// Discord returns at most this many messages per history request.
+const MAX_MESSAGES: u8 = 100;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("backfill")
+        .description("Fill a channel's context with its recent history from Discord")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(CreateCommandOption::new(CommandOptionType::Channel, "channel", "The channel to backfill, this one by default"))
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "n", "How many recent messages to fetch")
+                .min_int_value(1)
+                .max_int_value(MAX_MESSAGES as u64)
+        )
+}
+
+/// Fetched messages are stored as already reviewed, so they only serve as context.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let channel_id = channel_option(command, "channel").unwrap_or(command.channel_id);
+    let n = integer_option(command, "n").map_or(50, |x| x as u8);
+    defer(ctx, command).await;
+    let history = match channel_id.messages(&ctx.http, GetMessages::new().limit(n)).await {
+        Ok(history) => history,
+        Err(why) => {
+            warn!("failed to fetch the history of {channel_id}: {why}");
+            edit(ctx, command, format!("Could not fetch the history of <#{channel_id}>: {why}")).await;
+            return;
+        }
+    };
+    let fetched = history.len();
+    let messages = history.into_iter()
+        .filter(|x| !handler.ignores(&x.author))
+        .map(|x| PartialMessage {
+            status: ValidationStatus::Validated,
+            // Fetched messages don't carry their guild.
+            guild_id: command.guild_id.map(|x| x.get()),
+            ..PartialMessage::from(x)
+        })
+        .collect::<Vec<PartialMessage>>();
+    let stored = messages.len();
+    handler.database.send(DatabaseMessage::Backfill { channel_id: channel_id.get(), messages });
+    info!("{} backfilled {stored} messages in {channel_id}", command.user.id);
+    edit(ctx, command, format!("Fetched {fetched} messages from <#{channel_id}>; the {stored} not from ignored bots were merged into its context.")).await;
+}
@@ -0,0 +1,24 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{database::definitions::DatabaseMessage, Handler};
+use super::{reply, user_option};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("clearwarnings")
+        .description("Reset a user's warnings in this server")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "The user whose warnings to clear")
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let (Some(guild_id), Some(user_id)) = (command.guild_id, user_option(command, "user")) else {
+        return;
+    };
+    handler.database.send(DatabaseMessage::ClearWarnings { guild_id: guild_id.get(), user_id: user_id.get() });
+    info!("{} cleared the warnings of {user_id}", command.user.id);
+    reply(ctx, command, format!("Cleared the warnings of <@{user_id}>.")).await;
+}
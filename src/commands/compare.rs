@@ -0,0 +1,74 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use crate::{truncate, Handler, Outcome, Verdict, PROMPT_CONTEXT};
+use super::{defer, edit, reply, require_owner, string_option, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("compare")
+        .description("Run this channel's context through two models and compare their verdicts")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "model_a", "Model name as the provider expects it")
+                .required(true)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "model_b", "Model name as the provider expects it")
+                .required(true)
+        )
+}
+
+/// Both models see the same context and neither verdict is acted on.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let model_a = string_option(command, "model_a").unwrap_or_default().trim();
+    let model_b = string_option(command, "model_b").unwrap_or_default().trim();
+    if model_a.is_empty() || model_b.is_empty() {
+        reply(ctx, command, "Model names cannot be empty.").await;
+        return;
+    }
+    let messages = handler.get_latest(command.channel_id.get(), PROMPT_CONTEXT);
+    if messages.is_empty() {
+        reply(ctx, command, "There are no stored messages for this channel yet.").await;
+        return;
+    }
+    defer(ctx, command).await;
+    let count = messages.len();
+    let (a, b) = tokio::join!(
+        handler.verdict(messages.clone(), Some(model_a)),
+        handler.verdict(messages, Some(model_b))
+    );
+    let Some(judged) = a.as_ref().or(b.as_ref()).map(|x| x.newest.id) else {
+        edit(ctx, command, "Neither model returned a verdict for this channel's context.").await;
+        return;
+    };
+    let content = format!(
+        "Compared on the latest {count} messages, judging message {judged}:\n{}\n{}",
+        describe(model_a, a.as_ref()),
+        describe(model_b, b.as_ref())
+    );
+    edit(ctx, command, truncate(&content, MAX_REPLY_LEN)).await;
+}
+
+/// One model's line of the comparison, or an error line when it returned no verdict.
+fn describe(model: &str, verdict: Option<&Verdict>) -> String {
+    let Some(verdict) = verdict else {
+        return format!("- `{model}`: error, no verdict was returned");
+    };
+    let outcome = match &verdict.outcome {
+        Outcome::Failed(why) => format!("failed: {why}"),
+        Outcome::Unparsed => "no parseable verdict".into(),
+        Outcome::Unusable(why) => format!("unusable verdict: {why}"),
+        Outcome::Fine => "not flagged".into(),
+        Outcome::Flagged { target, reason, severity, confidence } => {
+            let severity = severity.map_or("unrated".into(), |x| format!("{x:?}").to_lowercase());
+            let confidence = confidence.map_or("unknown".into(), |x| format!("{x:.2}"));
+            format!("**flagged** message {} by <@{}> ({severity}, confidence {confidence}): {reason}", target.id, target.author_id)
+        }
+    };
+    let usage = verdict.usage.map_or("tokens not reported".into(), |x| {
+        format!("{} prompt + {} completion tokens", x.prompt_tokens, x.completion_tokens)
+    });
+    format!("- `{model}`: {outcome}\n  {:?}, {usage}", verdict.latency)
+}
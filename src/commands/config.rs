@@ -0,0 +1,32 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::{config, truncate};
+use super::{follow_up, reply, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("config")
+        .description("Show the bot's effective configuration (secrets are never shown)")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+}
+
+/// The summary is far longer than one message, so it is split into code blocks of whole
+/// lines, the first sent as the reply and the rest as follow-ups.
+pub async fn run(ctx: &Context, command: &CommandInteraction) {
+    let mut blocks = vec![String::new()];
+    for (name, value) in config::get().summary() {
+        let line = truncate(&format!("{name}: {value}"), MAX_REPLY_LEN / 2);
+        let block = blocks.last_mut().unwrap();
+        if !block.is_empty() && block.len() + line.len() + 1 > MAX_REPLY_LEN {
+            blocks.push(line);
+        }else{
+            if !block.is_empty() {
+                block.push('\n');
+            }
+            block.push_str(&line);
+        }
+    }
+    let mut blocks = blocks.into_iter().map(|x| format!("```\n{x}\n```"));
+    reply(ctx, command, blocks.next().unwrap_or_default()).await;
+    for block in blocks {
+        follow_up(ctx, command, block).await;
+    }
+}
@@ -0,0 +1,30 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use tracing::info;
+use crate::{database::definitions::DatabaseStats, Handler};
+use super::{reply, require_owner};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("db-restart")
+        .description("Restart the database thread, keeping stored messages and the context size")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let before = stored_messages(handler.stats());
+    handler.database.drain_and_restart();
+    let stats = handler.stats();
+    let context_size = stats.context_size;
+    let after = stored_messages(stats);
+    info!("{} restarted the database, {before} messages before and {after} after", command.user.id);
+    reply(ctx, command, format!(
+        "Restarted the database. {before} messages were stored before and {after} are now, with a context size of {}.",
+        context_size
+    )).await;
+}
+
+fn stored_messages(stats: DatabaseStats) -> usize {
+    stats.channels.iter().map(|x| x.messages).sum()
+}
@@ -0,0 +1,30 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::{config, truncate, Handler};
+use super::{reply, require_owner, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("dbstats")
+        .description("Show how many messages are stored per channel and how much memory they take")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let database = handler.stats();
+    let mut stats = database.channels;
+    stats.sort_by_key(|x| std::cmp::Reverse(x.messages));
+    let config = config::get();
+    let total = stats.iter().map(|x| x.messages).sum::<usize>();
+    let bytes = stats.iter().map(|x| x.bytes).sum::<usize>();
+    let lines = stats.iter()
+        .map(|x| format!("<#{}>: {} messages from <t:{}:R> to <t:{}:R>, ~{} KiB", x.channel_id, x.messages, x.oldest, x.newest, x.bytes.div_ceil(1024)))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let content = format!(
+        "CONTEXT_SIZE is {} with {:?} eviction. {total} messages in {} channels, ~{} KiB.\n{lines}",
+        database.context_size, config.context_eviction, stats.len(), bytes.div_ceil(1024)
+    );
+    reply(ctx, command, truncate(&content, MAX_REPLY_LEN)).await;
+}
@@ -0,0 +1,71 @@
+use serde_json::json;
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateAttachment, CreateCommand, CreateCommandOption, EditInteractionResponse, Permissions};
+use tracing::warn;
+use crate::{prompt, Handler, Validation, PROMPT_CONTEXT};
+use super::{defer, edit, reply, require_owner, string_option};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("debug-check")
+        .description("Validate a stored message and show the exact prompt and raw model response")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "message_id", "Id of the stored message to check")
+                .required(true)
+        )
+}
+
+/// Runs the same request `ai_request` would for the message, without acting on the verdict.
+/// Context summaries are left out so the prompt only contains stored messages.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let Some(id) = string_option(command, "message_id").and_then(|x| x.trim().parse::<u64>().ok()) else {
+        reply(ctx, command, "That is not a valid message id.").await;
+        return;
+    };
+    let channel_id = command.channel_id.get();
+    let stored = handler.get_latest(channel_id, u8::MAX);
+    let Some(position) = stored.iter().position(|x| x.id == id) else {
+        reply(ctx, command, format!("Message {id} is not stored for this channel.")).await;
+        return;
+    };
+    defer(ctx, command).await;
+    let start = (position + 1).saturating_sub(PROMPT_CONTEXT as usize);
+    let messages = &stored[start..=position];
+    let strictness = handler.channel_strictness(channel_id);
+    let profile = handler.channel_profile(channel_id);
+    let (rules, base) = handler.prompt_and_sampling();
+    let ai_messages = prompt::build(messages, &rules, strictness, profile, None, handler.pseudonyms().as_deref_mut());
+    let prompt_json = serde_json::to_value(&ai_messages).unwrap();
+    let report = match handler.chat(ai_messages, strictness.sampling(profile.map_or(base, |x| x.sampling(base)))).await {
+        Ok(response) => {
+            let validations = response.choices.iter()
+                .map(|x| Validation::parse(&x.content).map_err(|why| why.to_string()))
+                .map(|x| match x {
+                    Ok(validation) => json!(validation),
+                    Err(why) => json!({ "error": why })
+                })
+                .collect::<Vec<serde_json::Value>>();
+            json!({
+                "prompt": prompt_json,
+                "raw": response.choices,
+                "parsed": validations,
+                "usage": response.usage
+            })
+        },
+        Err(why) => json!({
+            "prompt": prompt_json,
+            "error": why.to_string()
+        })
+    };
+    let file = CreateAttachment::bytes(serde_json::to_vec_pretty(&report).unwrap(), format!("debug-check-{id}.json"));
+    let response = EditInteractionResponse::new()
+        .content(format!("Checked message {id} with {} messages of context ({strictness:?}).", messages.len()))
+        .new_attachment(file);
+    if let Err(why) = command.edit_response(&ctx.http, response).await {
+        warn!("failed to edit response to /{}: {why}", command.data.name);
+        edit(ctx, command, "Could not attach the debug report.").await;
+    }
+}
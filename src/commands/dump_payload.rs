@@ -0,0 +1,47 @@
+use serenity::all::{CommandInteraction, Context, CreateAttachment, CreateCommand, EditInteractionResponse, Permissions};
+use tracing::warn;
+use crate::{prompt, provider::fireworks, truncate, Handler, PROMPT_CONTEXT};
+use super::{defer, edit, reply, require_owner};
+
+/// Characters of each message kept in the dump, enough to spot malformed fields.
+const CONTENT_LEN: usize = 300;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("dump-payload")
+        .description("Show the Fireworks request body built for this channel's context")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+}
+
+/// Builds the body the next check in this channel would send, without sending it.
+/// The API key travels in a header, so the body has nothing to redact.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let channel_id = command.channel_id.get();
+    let messages = handler.get_latest(channel_id, PROMPT_CONTEXT);
+    if messages.is_empty() {
+        reply(ctx, command, "There are no stored messages for this channel yet.").await;
+        return;
+    }
+    defer(ctx, command).await;
+    let strictness = handler.channel_strictness(channel_id);
+    let profile = handler.channel_profile(channel_id);
+    let (rules, base) = handler.prompt_and_sampling();
+    let mut ai_messages = prompt::build(&messages, &rules, strictness, profile, None, handler.pseudonyms().as_deref_mut());
+    for message in &mut ai_messages {
+        message.content = message.content.as_deref().map(|x| truncate(x, CONTENT_LEN));
+    }
+    let sampling = strictness.sampling(profile.map_or(base, |x| x.sampling(base)));
+    let model = handler.model.read().unwrap().clone();
+    let payload = fireworks::payload(&model, ai_messages, sampling);
+    let file = CreateAttachment::bytes(serde_json::to_vec_pretty(&payload).unwrap(), format!("payload-{channel_id}.json"));
+    let response = EditInteractionResponse::new()
+        .content(format!("The request body for {} messages of context, with each message cut to {CONTENT_LEN} characters.", messages.len()))
+        .new_attachment(file);
+    if let Err(why) = command.edit_response(&ctx.http, response).await {
+        warn!("failed to edit response to /{}: {why}", command.data.name);
+        edit(ctx, command, "Could not attach the payload.").await;
+    }
+}
@@ -0,0 +1,25 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::{truncate, Handler};
+use super::{reply, require_owner, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("errors")
+        .description("List the latest provider and parse errors")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let errors = handler.errors.lock().unwrap().iter()
+        .rev()
+        .map(|(timestamp, error)| format!("<t:{timestamp}:R> {error}"))
+        .collect::<Vec<String>>();
+    if errors.is_empty() {
+        reply(ctx, command, "No provider or parse errors since startup.").await;
+        return;
+    }
+    let content = format!("Latest errors, newest first:\n{}", errors.join("\n"));
+    reply(ctx, command, truncate(&content, MAX_REPLY_LEN)).await;
+}
@@ -0,0 +1,35 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{database::definitions::DatabaseMessage, Handler, PartialMessage, ValidationStatus};
+use super::{reply, require_owner, string_option};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("inject")
+        .description("Add a synthetic message from you to this channel's context")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "text", "Content of the synthetic message")
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let text = string_option(command, "text").unwrap_or_default();
+    // The interaction id is a unique snowflake that no real message in the channel will have.
+    let mut message = PartialMessage::new(
+        command.id.get(),
+        command.channel_id.get(),
+        command.user.id.get(),
+        text.into(),
+        ValidationStatus::NotValidated,
+        command.id.created_at().unix_timestamp()
+    );
+    message.guild_id = command.guild_id.map(|x| x.get());
+    handler.database.send(DatabaseMessage::InsertMessage(message));
+    info!("{} injected a message into channel {}", command.user.id, command.channel_id);
+    reply(ctx, command, "Injected. Use /recheck to validate the context including it.").await;
+}
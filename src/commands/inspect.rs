@@ -0,0 +1,29 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use crate::{truncate, Handler};
+use super::{reply, string_option, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("inspect")
+        .description("Show what the bot stored for a message in this channel")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "message_id", "Id of the message to inspect")
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let Some(id) = string_option(command, "message_id").and_then(|x| x.trim().parse::<u64>().ok()) else {
+        reply(ctx, command, "That is not a valid message id.").await;
+        return;
+    };
+    let content = match handler.get_message(command.channel_id.get(), id) {
+        Some(message) => {
+            let json = serde_json::to_string_pretty(&message).unwrap();
+            format!("```json\n{}\n```", truncate(&json, MAX_REPLY_LEN))
+        },
+        None => format!("Message {id} is not stored for this channel.")
+    };
+    reply(ctx, command, content).await;
+}
@@ -0,0 +1,23 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::metrics;
+use super::{reply, require_owner};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("latency")
+        .description("Show recent processing latency percentiles")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let lines = metrics::get().latencies().iter()
+        .map(|(name, latency)| match latency.percentiles() {
+            Some([(_, p50), (_, p90), (_, p99)]) => format!("{name}: p50 {p50:?}, p90 {p90:?}, p99 {p99:?}"),
+            None => format!("{name}: no samples yet")
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    reply(ctx, command, format!("```\n{lines}\n```")).await;
+}
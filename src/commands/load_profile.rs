@@ -0,0 +1,38 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{truncate, Handler};
+use super::{reply, require_owner, save_profile::MAX_NAME_LEN, string_option, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("load-profile")
+        .description("Switch to a prompt saved with /save-profile until the next restart")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "name", "Name the snapshot was saved under")
+                .max_length(MAX_NAME_LEN as u16)
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let name = string_option(command, "name").unwrap_or_default().trim();
+    let mut snapshots = handler.prompt_snapshots();
+    let Some(snapshot) = snapshots.remove(name) else {
+        let mut names = snapshots.into_keys().map(|x| format!("`{x}`")).collect::<Vec<String>>();
+        names.sort();
+        let saved = if names.is_empty() { "none".into() } else { names.join(", ") };
+        reply(ctx, command, truncate(&format!("There is no snapshot named `{name}`. Saved: {saved}."), MAX_REPLY_LEN)).await;
+        return;
+    };
+    let previous = std::mem::replace(&mut *handler.model.write().unwrap(), snapshot.model.clone());
+    let content = format!(
+        "Loaded `{name}`, saved <t:{}:R>: `{}` at temperature {} and {} max tokens, replacing `{previous}`.",
+        snapshot.saved_at, snapshot.model, snapshot.sampling.temperature, snapshot.sampling.max_tokens
+    );
+    *handler.prompt_snapshot.write().unwrap() = Some(snapshot);
+    info!("{} loaded the prompt snapshot {name}", command.user.id);
+    reply(ctx, command, content).await;
+}
@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use crate::{database::definitions::DatabaseMessage, Handler, PartialMessage, ValidationStatus};
+use super::{integer_option, reply, require_owner};
+
+const MAX_COUNT: u64 = 100_000;
+/// Bits below a snowflake's timestamp, enough to number `MAX_COUNT` messages.
+const SNOWFLAKE_TIME_SHIFT: u32 = 22;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("loadtest")
+        .description("Insert synthetic messages into this channel's context")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "count", "How many messages to insert")
+                .min_int_value(1)
+                .max_int_value(MAX_COUNT)
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let count = integer_option(command, "count").unwrap_or(1) as u64;
+    let channel_id = command.channel_id.get();
+    let author_id = command.user.id.get();
+    let timestamp = command.id.created_at().unix_timestamp();
+    // Synthetic ids are snowflakes from the moment the command was sent, numbered in the bits
+    // Discord uses for the worker, process and increment, so CONTEXT_ORDER=id sorts them among real messages.
+    let first_id = command.id.get() >> SNOWFLAKE_TIME_SHIFT << SNOWFLAKE_TIME_SHIFT;
+    let started = Instant::now();
+    for i in 0..count {
+        let message = PartialMessage::new(first_id | i, channel_id, author_id, format!("loadtest message {i}"), ValidationStatus::NotValidated, timestamp);
+        handler.database.send(DatabaseMessage::InsertMessage(message));
+    }
+    let stored = handler.get_latest(channel_id, u8::MAX).len();
+    let elapsed = started.elapsed();
+    reply(ctx, command, format!(
+        "Inserted {count} messages in {elapsed:?}. The channel now holds at least {stored} messages (CONTEXT_SIZE is {}).",
+        handler.stats().context_size
+    )).await;
+}
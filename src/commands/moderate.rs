@@ -0,0 +1,40 @@
+use serenity::all::{CommandInteraction, CommandType, Context, CreateCommand, GetMessages, Permissions, ResolvedTarget};
+use tracing::warn;
+use crate::{Handler, PartialMessage, PROMPT_CONTEXT};
+use super::{defer, edit, reply};
+
+/// Context-menu commands are named by what the menu shows.
+pub const NAME: &str = "Moderate message";
+
+/// A message context-menu command rather than a link option: Discord includes the target's
+/// content in the interaction, which REST fetches leave empty without the message content
+/// intent that `COMMAND_ONLY` drops. The target is also always in the invoking guild.
+pub fn register() -> CreateCommand {
+    CreateCommand::new(NAME)
+        .kind(CommandType::Message)
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let (Some(ResolvedTarget::Message(target)), Some(guild_id)) = (command.data.target(), command.guild_id) else {
+        reply(ctx, command, "Use this on a message in a server.").await;
+        return;
+    };
+    defer(ctx, command).await;
+    let (channel_id, message_id) = (target.channel_id, target.id);
+    let earlier = channel_id.messages(&ctx.http, GetMessages::new().before(message_id).limit(PROMPT_CONTEXT - 1)).await
+        .inspect_err(|why| warn!("failed to fetch context before {message_id} in {channel_id}: {why}"))
+        .unwrap_or_default();
+    // Fetched messages arrive newest first and without a guild id. Without the message content
+    // intent their content is empty, and empty messages would only mislead the model.
+    let messages = earlier.into_iter().rev().filter(|x| !x.content.is_empty())
+        .chain(std::iter::once(target.clone()))
+        .map(|message| PartialMessage {
+            guild_id: Some(guild_id.get()),
+            ..PartialMessage::from(message)
+        })
+        .collect::<Vec<PartialMessage>>();
+    handler.ai_request(ctx, messages).await;
+    edit(ctx, command, format!("Validated message {message_id}.")).await;
+}
@@ -0,0 +1,37 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::{truncate, Handler};
+use super::{reply, require_owner, MAX_REPLY_LEN};
+
+/// How many of the latest failures are shown as examples.
+const EXAMPLES: usize = 5;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("parse-stats")
+        .description("Show how often recent model responses parsed into a verdict")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let (total, failures) = {
+        let parses = handler.parses.lock().unwrap();
+        let failures = parses.iter().rev().filter_map(|x| x.as_ref().err().cloned()).collect::<Vec<String>>();
+        (parses.len(), failures)
+    };
+    if total == 0 {
+        reply(ctx, command, "No model responses have been parsed since startup.").await;
+        return;
+    }
+    let parsed = total - failures.len();
+    let mut content = format!(
+        "{parsed} of the latest {total} choices parsed into a verdict ({:.1}%).",
+        parsed as f64 * 100.0 / total as f64
+    );
+    if !failures.is_empty() {
+        let examples = failures.iter().take(EXAMPLES).map(|x| format!("- {x}")).collect::<Vec<String>>().join("\n");
+        content.push_str(&format!("\nLatest failures, newest first:\n{examples}"));
+    }
+    reply(ctx, command, truncate(&content, MAX_REPLY_LEN)).await;
+}
@@ -0,0 +1,27 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::{truncate, Handler, ValidationStatus};
+use super::{reply, MAX_REPLY_LEN};
+
+/// Characters of each message's content shown in the listing.
+const PREVIEW_LEN: usize = 80;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("pending")
+        .description("List stored messages in this channel that have not been validated yet")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let messages = handler.get_by_status(command.channel_id.get(), ValidationStatus::NotValidated);
+    if messages.is_empty() {
+        reply(ctx, command, "Every stored message in this channel has been validated.").await;
+        return;
+    }
+    let lines = messages.iter()
+        .map(|x| format!("<t:{}:T> `{}` <@{}>: {}", x.timestamp, x.id, x.author_id, truncate(&x.content, PREVIEW_LEN)))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let content = format!("{} messages are pending validation:\n{lines}", messages.len());
+    reply(ctx, command, truncate(&content, MAX_REPLY_LEN)).await;
+}
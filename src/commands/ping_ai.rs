@@ -0,0 +1,37 @@
+use std::time::Instant;
+
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::{config, prompt, AIMessage, Handler};
+use super::{defer, edit, require_owner};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("ping-ai")
+        .description("Measure the round-trip latency of the configured AI provider")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+/// The smallest request a provider answers, also used to test a key before `/rotatekey` swaps it in.
+pub fn ping() -> Vec<AIMessage> {
+    prompt::with_instructions("Reply with the JSON object {\"pong\": true}.".into(), vec![AIMessage::new(Some("ping".into()), "user".into())])
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    defer(ctx, command).await;
+    let started = Instant::now();
+    let response = handler.chat(ping(), config::get().sampling).await;
+    let elapsed = started.elapsed();
+    let name = handler.provider.name();
+    let content = match response {
+        Ok(response) => {
+            let usage = response.usage.map_or("not reported".into(), |x| {
+                format!("{} prompt + {} completion", x.prompt_tokens, x.completion_tokens)
+            });
+            format!("{name} answered in {elapsed:?}. Tokens: {usage}.")
+        },
+        Err(why) => format!("{name} failed after {elapsed:?}: {why}")
+    };
+    edit(ctx, command, content).await;
+}
@@ -0,0 +1,35 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage, Permissions};
+use tracing::warn;
+use crate::{enforcement, PartialMessage, Severity, ValidationStatus};
+use super::require_owner;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("preview-log")
+        .description("Show the embed posted for a flag, using a sample message from you")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+}
+
+pub async fn run(ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let mut sample = PartialMessage::new(
+        command.id.get(),
+        command.channel_id.get(),
+        command.user.id.get(),
+        "This is a sample message.".into(),
+        ValidationStatus::NotValidated,
+        command.id.created_at().unix_timestamp()
+    );
+    sample.guild_id = command.guild_id.map(|x| x.get());
+    let embed = enforcement::flag_embed(&sample, "Sample reason explaining why the message was flagged", Some(Severity::Medium), Some(0.9))
+        .title("Preview of a flag")
+        .field("Action taken", "Deleted", true);
+    let message = CreateInteractionResponseMessage::new()
+        .embed(embed)
+        .ephemeral(true);
+    if let Err(why) = command.create_response(&ctx.http, CreateInteractionResponse::Message(message)).await {
+        warn!("failed to respond to /{}: {why}", command.data.name);
+    }
+}
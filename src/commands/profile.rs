@@ -0,0 +1,39 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{config, Handler};
+use super::{channel_option, reply, string_option};
+
+pub fn register() -> CreateCommand {
+    // Discord allows at most 25 choices per option.
+    let name = config::get().profiles.iter().take(25).fold(
+        CreateCommandOption::new(CommandOptionType::String, "name", "The profile to use, back to the default when omitted"),
+        |option, profile| option.add_string_choice(&profile.name, &profile.name)
+    );
+    CreateCommand::new("profile")
+        .description("Set the moderation profile a channel uses, kept across restarts")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "channel", "The channel to set the profile of")
+                .required(true)
+        )
+        .add_option(name)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let Some(channel_id) = channel_option(command, "channel") else {
+        return;
+    };
+    let name = string_option(command, "name");
+    if let Some(name) = name.filter(|x| config::get().profile(x).is_none()) {
+        reply(ctx, command, format!("There is no profile named `{name}` in PROFILES.")).await;
+        return;
+    }
+    handler.set_channel_profile(channel_id.get(), name.map(String::from));
+    info!("{} set the profile of {channel_id} to {name:?}", command.user.id);
+    let content = match name {
+        Some(name) => format!("<#{channel_id}> now uses the `{name}` profile."),
+        None => format!("<#{channel_id}> now uses the default profile.")
+    };
+    reply(ctx, command, content).await;
+}
@@ -0,0 +1,36 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use crate::{Handler, ValidationStatus, PROMPT_CONTEXT};
+use super::{defer, edit, integer_option, reply};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("recheck")
+        .description("Re-run moderation over the latest messages in this channel")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "n", "How many recent messages to recheck")
+                .min_int_value(1)
+                .max_int_value(u8::MAX as u64)
+        )
+}
+
+/// Each of the latest messages is judged in turn against the messages stored before it,
+/// like `/shadow-report` does, and every flag is acted on.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let n_latest = integer_option(command, "n").map(|n| n as u8).unwrap_or(PROMPT_CONTEXT);
+    // The oldest rechecked message still gets its full context.
+    let stored = handler.get_latest(command.channel_id.get(), n_latest.saturating_add(PROMPT_CONTEXT - 1));
+    if stored.is_empty() {
+        reply(ctx, command, "There are no stored messages for this channel yet.").await;
+        return;
+    }
+    defer(ctx, command).await;
+    let first = stored.len().saturating_sub(n_latest as usize);
+    for i in first..stored.len() {
+        let mut context = stored[(i + 1).saturating_sub(PROMPT_CONTEXT as usize)..=i].to_vec();
+        // Judged as new, since only a message that is not yet reviewed can be flagged.
+        context.last_mut().unwrap().status = ValidationStatus::NotValidated;
+        handler.ai_request(ctx, context).await;
+    }
+    edit(ctx, command, format!("Rechecked the latest {} messages.", stored.len() - first)).await;
+}
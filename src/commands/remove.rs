@@ -0,0 +1,55 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::{info, warn};
+use crate::{enforcement, Handler, PartialMessage};
+use super::{defer, edit, guild_message_link, string_option};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("remove")
+        .description("Delete a message and record it like a flag, attributed to you")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "message_link", "Link to the message to remove")
+                .required(true)
+        )
+        .add_option(CreateCommandOption::new(CommandOptionType::String, "reason", "Why the message is removed"))
+}
+
+/// Goes through the same webhook, warning and escalation steps as a flag,
+/// but always deletes regardless of `ENFORCEMENT_MODE` and skips the flag cooldown.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    defer(ctx, command).await;
+    let link = string_option(command, "message_link").unwrap_or_default();
+    let Some((guild_id, channel_id, message_id)) = guild_message_link(ctx, command, link).await else {
+        return;
+    };
+    let original = match channel_id.message(&ctx.http, message_id).await {
+        Ok(message) => message,
+        Err(why) => {
+            warn!("failed to fetch message {message_id} in {channel_id}: {why}");
+            edit(ctx, command, "Could not fetch that message.").await;
+            return;
+        }
+    };
+    let message = PartialMessage {
+        guild_id: Some(guild_id.get()),
+        ..PartialMessage::from(original)
+    };
+    let moderator = command.user.id;
+    let reason = string_option(command, "reason").map(str::trim).filter(|x| !x.is_empty()).unwrap_or("Removed by a moderator");
+    let audit_reason = format!("{reason} (removed by {})", command.user.name);
+    if !enforcement::delete(ctx, &message, &audit_reason).await {
+        edit(ctx, command, "Could not delete that message.").await;
+        return;
+    }
+    info!("{moderator} removed message {message_id} from {}: {reason}", message.author_id);
+    enforcement::post_webhook(&handler.web_client, &message, reason, None, None);
+    let embed = enforcement::flag_embed(&message, reason, None, None)
+        .title("Message removed by a moderator")
+        .field("Action taken", "Deleted", true)
+        .field("Removed by", format!("<@{moderator}>"), true)
+        .description(&message.content);
+    enforcement::notify_mods(ctx, handler.log_channel(message.guild_id), embed).await;
+    handler.warn_author(ctx, &message, reason, None).await;
+    edit(ctx, command, format!("Removed message {message_id}.")).await;
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use serenity::all::{ChannelId, CommandInteraction, CommandOptionType, Context, CreateAllowedMentions, CreateCommand, CreateCommandOption, CreateMessage, Permissions};
+use tracing::warn;
+use crate::{config, truncate, Handler, ValidationStatus};
+use super::{channel_option, defer, edit, reply, require_owner, MAX_REPLY_LEN};
+
+/// Pause between reposts, which keeps a long replay well under Discord's rate limits.
+const REPOST_DELAY: Duration = Duration::from_secs(1);
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("replay")
+        .description("Repost a channel's stored context into the replay channel")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "channel", "The channel whose context to replay")
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let Some(channel_id) = channel_option(command, "channel") else {
+        return;
+    };
+    let Some(replay_channel) = config::get().replay_channel_id.map(ChannelId::new) else {
+        reply(ctx, command, "REPLAY_CHANNEL_ID is not set.").await;
+        return;
+    };
+    let messages = handler.get_latest(channel_id.get(), u8::MAX);
+    if messages.is_empty() {
+        reply(ctx, command, format!("There are no stored messages for <#{channel_id}>.")).await;
+        return;
+    }
+    defer(ctx, command).await;
+    let header = format!("Replay of the {} stored messages of <#{channel_id}>:", messages.len());
+    if !repost(ctx, replay_channel, &header).await {
+        edit(ctx, command, format!("Could not post in <#{replay_channel}>.")).await;
+        return;
+    }
+    let mut reposted = 0;
+    for message in &messages {
+        tokio::time::sleep(REPOST_DELAY).await;
+        let reviewed = if message.status == ValidationStatus::Validated { "reviewed" } else { "not reviewed" };
+        let line = format!("**<@{}>** <t:{}:T> ({reviewed}): {}", message.author_id, message.timestamp, message.content);
+        if !repost(ctx, replay_channel, &line).await {
+            break;
+        }
+        reposted += 1;
+    }
+    edit(ctx, command, format!("Replayed {reposted} of {} messages into <#{replay_channel}>.", messages.len())).await;
+}
+
+/// Posts `content` without pinging anyone it mentions. Returns whether it was posted.
+async fn repost(ctx: &Context, channel_id: ChannelId, content: &str) -> bool {
+    let message = CreateMessage::new()
+        .content(truncate(content, MAX_REPLY_LEN))
+        .allowed_mentions(CreateAllowedMentions::new());
+    match channel_id.send_message(&ctx.http, message).await {
+        Ok(_) => true,
+        Err(why) => {
+            warn!("failed to replay a message into {channel_id}: {why}");
+            false
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, InteractionContext};
+use tracing::{info, warn};
+use crate::{config::{self, ProviderKind}, provider::fireworks::FireworksProvider, Handler};
+use super::{defer, edit, ping_ai, reply, require_owner, string_option};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("rotatekey")
+        .description("Replace the Fireworks API key without restarting, after testing the new one")
+        .contexts(vec![InteractionContext::BotDm])
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "key", "The new API key")
+                .required(true)
+        )
+}
+
+/// The key is only ever sent to the provider; it is never logged or repeated back.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    if command.guild_id.is_some() {
+        reply(ctx, command, "API keys can only be rotated in DMs with the bot.").await;
+        return;
+    }
+    let config = config::get();
+    if config.provider != ProviderKind::Fireworks {
+        reply(ctx, command, "The configured provider does not use an API key.").await;
+        return;
+    }
+    let Some(key) = string_option(command, "key").map(str::trim).filter(|x| !x.is_empty()) else {
+        reply(ctx, command, "The key cannot be empty.").await;
+        return;
+    };
+    defer(ctx, command).await;
+    let model = handler.model.read().unwrap().clone();
+    let candidate = FireworksProvider::new(key.into());
+    if let Err(why) = handler.chat_with(&candidate, &model, ping_ai::ping(), config.sampling).await {
+        warn!("{} tried to rotate to an API key that failed its test request", command.user.id);
+        edit(ctx, command, format!("The new key was not accepted, keeping the current one: {why}")).await;
+        return;
+    }
+    handler.provider.set_api_key(key.into());
+    // A fallback without its own key shares the primary's, so it rotates along with it.
+    if let Some((fallback, _)) = &handler.fallback {
+        if config.fallback_fireworks_api_key.is_none() {
+            fallback.set_api_key(key.into());
+        }
+    }
+    info!("{} rotated the API key", command.user.id);
+    edit(ctx, command, "The new key works and is now in use. Remember to update FIREWORKS_API_KEY before the next restart.").await;
+}
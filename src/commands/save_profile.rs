@@ -0,0 +1,43 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions, Timestamp};
+use tracing::info;
+use crate::{database::{definitions::DatabaseMessage, state::PromptSnapshot}, Handler};
+use super::{reply, require_owner, string_option};
+
+/// Longest snapshot name, so `/load-profile` replies stay readable.
+pub const MAX_NAME_LEN: usize = 32;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("save-profile")
+        .description("Save the current prompt, model and sampling parameters under a name")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "name", "Name to save it under, replacing any snapshot with that name")
+                .max_length(MAX_NAME_LEN as u16)
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let name = string_option(command, "name").unwrap_or_default().trim();
+    if name.is_empty() {
+        reply(ctx, command, "The name cannot be empty.").await;
+        return;
+    }
+    let (system_prompt, sampling) = handler.prompt_and_sampling();
+    let model = handler.model.read().unwrap().clone();
+    let snapshot = PromptSnapshot {
+        system_prompt,
+        model: model.clone(),
+        sampling,
+        saved_at: Timestamp::now().unix_timestamp()
+    };
+    handler.database.send(DatabaseMessage::SavePromptSnapshot { name: name.into(), snapshot });
+    info!("{} saved the prompt snapshot {name}", command.user.id);
+    reply(ctx, command, format!(
+        "Saved the current prompt with `{model}` at temperature {} and {} max tokens as `{name}`.",
+        sampling.temperature, sampling.max_tokens
+    )).await;
+}
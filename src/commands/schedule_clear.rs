@@ -0,0 +1,39 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{database::definitions::DatabaseMessage, Handler};
+use super::{channel_option, integer_option, reply};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("schedule-clear")
+        .description("Periodically clear the stored context of a channel")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "channel", "The channel to clear")
+                .required(true)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "minutes", "Clear every this many minutes, stops clearing when omitted")
+                .min_int_value(1)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let Some(channel_id) = channel_option(command, "channel") else {
+        return;
+    };
+    let minutes = integer_option(command, "minutes");
+    let interval_secs = minutes.map(|x| x as u64 * 60);
+    handler.database.send(DatabaseMessage::SetClearSchedule { channel_id: channel_id.get(), interval_secs });
+    let content = match minutes {
+        Some(minutes) => {
+            info!("{} scheduled {channel_id} to be cleared every {minutes} minutes", command.user.id);
+            format!("The context of <#{channel_id}> will be cleared every {minutes} minutes.")
+        },
+        None => {
+            info!("{} stopped clearing {channel_id} on a schedule", command.user.id);
+            format!("The context of <#{channel_id}> is no longer cleared on a schedule.")
+        }
+    };
+    reply(ctx, command, content).await;
+}
@@ -0,0 +1,28 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::Handler;
+use super::{integer_option, reply, require_owner};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("setcontext")
+        .description("Change how many messages are stored per channel until the bot restarts")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "n", "Messages to keep per channel")
+                .required(true)
+                .min_int_value(1)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let Some(context_size) = integer_option(command, "n").map(|x| x as usize) else {
+        return;
+    };
+    let previous = handler.stats().context_size;
+    handler.database.set_context_size(context_size);
+    info!("{} changed the context size from {previous} to {context_size}", command.user.id);
+    reply(ctx, command, format!("Channels now keep up to {context_size} messages (was {previous}).")).await;
+}
@@ -0,0 +1,30 @@
+use serenity::all::{ChannelType, CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{config, database::definitions::DatabaseMessage, Handler};
+use super::{channel_option, reply};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("setlogchannel")
+        .description("Choose where mods of this server are notified about flags")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "channel", "The log channel, resets to the default when omitted")
+                .channel_types(vec![ChannelType::Text])
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let Some(guild_id) = command.guild_id else {
+        return;
+    };
+    let channel_id = channel_option(command, "channel");
+    handler.database.send(DatabaseMessage::SetLogChannel { guild_id: guild_id.get(), channel_id: channel_id.map(|x| x.get()) });
+    info!("{} set the log channel of {guild_id} to {channel_id:?}", command.user.id);
+    let content = match (channel_id, config::get().log_channel_id) {
+        (Some(channel_id), _) => format!("Flags are now reported in <#{channel_id}>."),
+        (None, Some(default)) => format!("Flags are now reported in the default log channel, <#{default}>."),
+        (None, None) => "This server no longer has a log channel, flags are only logged by the bot.".into()
+    };
+    reply(ctx, command, content).await;
+}
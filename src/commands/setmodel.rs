@@ -0,0 +1,28 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::Handler;
+use super::{reply, require_owner, string_option};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("setmodel")
+        .description("Switch the model used for moderation until the next restart")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "name", "Model name as the provider expects it")
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let name = string_option(command, "name").unwrap_or_default().trim();
+    if name.is_empty() {
+        reply(ctx, command, "The model name cannot be empty.").await;
+        return;
+    }
+    let previous = std::mem::replace(&mut *handler.model.write().unwrap(), name.into());
+    info!("{} switched the model from {previous} to {name}", command.user.id);
+    reply(ctx, command, format!("Switched the model from `{previous}` to `{name}`.")).await;
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions, Timestamp};
+use crate::{Handler, Outcome, ValidationStatus, PROMPT_CONTEXT};
+use super::{defer, edit, integer_option, reply, require_owner};
+
+/// Upper bound on messages replayed by one report, since each one is a provider request.
+const MAX_CHECKS: usize = 100;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("shadow-report")
+        .description("Report how many recent messages moderation would have flagged, by severity, without acting")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "minutes", "How far back to look, 60 by default")
+                .min_int_value(1)
+                .max_int_value(24 * 60)
+        )
+}
+
+/// Every stored message from the window is judged as if it had just arrived, against the
+/// messages stored before it. Verdicts are only counted, never acted on or stored.
+/// Flags are broken down by severity, since verdicts carry no category.
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let minutes = integer_option(command, "minutes").unwrap_or(60);
+    let since = Timestamp::now().unix_timestamp() - minutes * 60;
+    let mut contexts = Vec::new();
+    for channel in handler.stats().channels {
+        let stored = handler.get_latest(channel.channel_id, u8::MAX);
+        for (i, message) in stored.iter().enumerate().filter(|(_, x)| x.timestamp >= since) {
+            let mut context = stored[(i + 1).saturating_sub(PROMPT_CONTEXT as usize)..=i].to_vec();
+            // Judged as new, so the model doesn't treat it as already reviewed.
+            context.last_mut().unwrap().status = ValidationStatus::NotValidated;
+            contexts.push((message.timestamp, context));
+        }
+    }
+    if contexts.is_empty() {
+        reply(ctx, command, format!("No stored messages are from the last {minutes} minutes.")).await;
+        return;
+    }
+    defer(ctx, command).await;
+    // The newest messages matter most when there are more than can be checked.
+    contexts.sort_by_key(|(timestamp, _)| std::cmp::Reverse(*timestamp));
+    let skipped = contexts.len().saturating_sub(MAX_CHECKS);
+    let (mut flagged, mut failed) = (0, 0);
+    let mut by_severity = BTreeMap::<String, usize>::new();
+    let checked = contexts.len() - skipped;
+    for (_, context) in contexts.into_iter().take(MAX_CHECKS) {
+        let Some(verdict) = handler.verdict(context, None).await else {
+            continue;
+        };
+        match verdict.outcome {
+            Outcome::Flagged { severity, .. } => {
+                flagged += 1;
+                *by_severity.entry(severity.map_or("unrated".into(), |x| format!("{x:?}").to_lowercase())).or_default() += 1;
+            },
+            Outcome::Failed(_) | Outcome::Unparsed => failed += 1,
+            Outcome::Unusable(_) | Outcome::Fine => {}
+        }
+    }
+    let mut content = format!("Over the last {minutes} minutes, {checked} messages were checked and {flagged} would have been flagged.");
+    if !by_severity.is_empty() {
+        let severities = by_severity.iter().map(|(name, count)| format!("{name}: {count}")).collect::<Vec<String>>().join(", ");
+        content.push_str(&format!("\nFlags by severity: {severities}."));
+    }
+    if failed > 0 {
+        content.push_str(&format!("\n{failed} checks got no usable answer from the provider."));
+    }
+    if skipped > 0 {
+        content.push_str(&format!("\n{skipped} older messages were not checked, past the limit of {MAX_CHECKS}."));
+    }
+    edit(ctx, command, content).await;
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{prompt::Strictness, Handler};
+use super::{channel_option, integer_option, reply, string_option};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("strictness")
+        .description("Temporarily make moderation stricter or more lenient in a channel")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Channel, "channel", "The channel to adjust")
+                .required(true)
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "level", "How strict moderation should be")
+                .required(true)
+                .add_string_choice("lenient", "lenient")
+                .add_string_choice("normal", "normal")
+                .add_string_choice("strict", "strict")
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "minutes", "Reset to normal after this many minutes")
+                .min_int_value(1)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let (Some(channel_id), Some(strictness)) = (
+        channel_option(command, "channel"),
+        string_option(command, "level").and_then(|x| x.parse::<Strictness>().ok())
+    ) else {
+        return;
+    };
+    let minutes = integer_option(command, "minutes");
+    let duration = minutes.map(|x| Duration::from_secs(x as u64 * 60));
+    handler.set_channel_strictness(channel_id.get(), strictness, duration);
+    info!("{} set the strictness of {channel_id} to {strictness:?}", command.user.id);
+    let content = match minutes {
+        Some(minutes) if strictness != Strictness::Normal => format!("<#{channel_id}> is now {strictness:?} for {minutes} minutes."),
+        _ => format!("<#{channel_id}> is now {strictness:?}.")
+    };
+    reply(ctx, command, content).await;
+}
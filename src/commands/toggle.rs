@@ -0,0 +1,26 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use tracing::info;
+use crate::{database::definitions::DatabaseMessage, Handler};
+use super::{boolean_option, reply};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("toggle")
+        .description("Turn moderation on or off for this server")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Boolean, "enabled", "Whether to moderate this server, flips the current state when omitted")
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let Some(guild_id) = command.guild_id else {
+        return;
+    };
+    let enabled = boolean_option(command, "enabled")
+        .unwrap_or_else(|| !handler.guild_settings(guild_id.get()).enabled);
+    handler.database.send(DatabaseMessage::SetGuildEnabled { guild_id: guild_id.get(), enabled });
+    info!("{} turned moderation {} in {guild_id}", command.user.id, if enabled { "on" } else { "off" });
+    let content = if enabled { "Moderation is now on for this server." } else { "Moderation is now off for this server." };
+    reply(ctx, command, content).await;
+}
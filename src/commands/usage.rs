@@ -0,0 +1,31 @@
+use serenity::all::{CommandInteraction, Context, CreateCommand, Permissions};
+use crate::{config, Handler};
+use super::{reply, require_owner};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("usage")
+        .description("Show today's AI requests and tokens against the daily budgets")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    if !require_owner(ctx, command).await {
+        return;
+    }
+    let config = config::get();
+    let content = {
+        let mut usage = handler.usage.lock().unwrap();
+        usage.roll_over();
+        let requests = match config.daily_request_budget {
+            Some(budget) => format!("{} of {budget}", usage.requests),
+            None => format!("{} (no budget)", usage.requests)
+        };
+        let tokens = match config.daily_token_budget {
+            Some(budget) => format!("{} of {budget}", usage.tokens),
+            None => format!("{} (no budget)", usage.tokens)
+        };
+        let state = if usage.exhausted() { "The budget is used up, messages are only checked with heuristics." } else { "Within budget." };
+        format!("Requests: {requests}\nTokens: {tokens}\n{state} Resets <t:{}:R>.", usage.resets_at())
+    };
+    reply(ctx, command, content).await;
+}
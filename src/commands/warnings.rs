@@ -0,0 +1,31 @@
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, Permissions};
+use crate::{enforcement, truncate, Handler};
+use super::{reply, user_option, MAX_REPLY_LEN};
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("warnings")
+        .description("List the warnings a user has received in this server")
+        .default_member_permissions(Permissions::MANAGE_MESSAGES)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "The user to look up")
+                .required(true)
+        )
+}
+
+pub async fn run(handler: &Handler, ctx: &Context, command: &CommandInteraction) {
+    let (Some(guild_id), Some(user_id)) = (command.guild_id, user_option(command, "user")) else {
+        return;
+    };
+    let warnings = handler.get_warnings(guild_id.get(), user_id.get());
+    if warnings.is_empty() {
+        reply(ctx, command, format!("<@{user_id}> has no warnings.")).await;
+        return;
+    }
+    let lines = warnings.iter()
+        .map(|x| format!("<t:{}:f> {}", x.timestamp, enforcement::shown_reason(&x.reason)))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let content = format!("<@{user_id}> has {} warnings:\n{lines}", warnings.len());
+    reply(ctx, command, truncate(&content, MAX_REPLY_LEN)).await;
+}
@@ -0,0 +1,999 @@
+use std::{fmt, net::SocketAddr, str::FromStr, sync::OnceLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+use crate::Severity;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProviderKind {
+    Fireworks,
+    Ollama
+}
+
+impl FromStr for ProviderKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fireworks" => Ok(Self::Fireworks),
+            "ollama" => Ok(Self::Ollama),
+            _ => Err(())
+        }
+    }
+}
+
+/// What happens to a message once it is flagged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnforcementMode {
+    Log,
+    Delete,
+    /// Re-post the message to the quarantine channel, then delete it.
+    Quarantine
+}
+
+impl FromStr for EnforcementMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "log" => Ok(Self::Log),
+            "delete" => Ok(Self::Delete),
+            "quarantine" => Ok(Self::Quarantine),
+            _ => Err(())
+        }
+    }
+}
+
+/// One `SEVERITY_ACTIONS` entry, such as `medium-high=delete+timeout`: flags rated from `from`
+/// to `to` are handled with `mode` instead of `ENFORCEMENT_MODE`, and time their author out as well.
+#[derive(Clone, Copy, Debug)]
+pub struct SeverityTier {
+    pub from: Severity,
+    pub to: Severity,
+    pub mode: EnforcementMode,
+    pub timeout: bool
+}
+
+impl SeverityTier {
+    fn overlaps(&self, other: &Self) -> bool {
+        self.from <= other.to && other.from <= self.to
+    }
+}
+
+impl FromStr for SeverityTier {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (range, action) = s.split_once('=').ok_or(())?;
+        let (from, to) = match range.split_once('-') {
+            Some((from, to)) => (from.parse()?, to.parse()?),
+            None => (range.parse()?, range.parse()?)
+        };
+        if from > to {
+            return Err(());
+        }
+        let (mode, timeout) = match action.trim().split_once('+') {
+            Some((mode, "timeout")) => (mode.parse()?, true),
+            Some(_) => return Err(()),
+            None => (action.parse()?, false)
+        };
+        Ok(Self { from, to, mode, timeout })
+    }
+}
+
+/// What to do with a flag whose confidence is below `UNCERTAIN_THRESHOLD`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UncertainAction {
+    Ignore,
+    Log,
+    /// Post the flag to the mod-log channel without acting on it.
+    Notify
+}
+
+impl FromStr for UncertainAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ignore" => Ok(Self::Ignore),
+            "log" => Ok(Self::Log),
+            "notify" => Ok(Self::Notify),
+            _ => Err(())
+        }
+    }
+}
+
+/// How the database decides which stored messages to drop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionStrategy {
+    /// Keep at most `CONTEXT_SIZE` messages per channel.
+    Count,
+    /// Drop messages older than `CONTEXT_TTL_SECS`.
+    Age,
+    /// Apply both limits.
+    Hybrid
+}
+
+impl FromStr for EvictionStrategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "count" => Ok(Self::Count),
+            "age" => Ok(Self::Age),
+            "hybrid" => Ok(Self::Hybrid),
+            _ => Err(())
+        }
+    }
+}
+
+/// How context is put in chronological order before it is sent to the model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContextOrder {
+    /// By creation time in seconds, keeping arrival order for ties.
+    Timestamp,
+    /// By snowflake id, which is strictly increasing.
+    Id
+}
+
+impl FromStr for ContextOrder {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "timestamp" => Ok(Self::Timestamp),
+            "id" => Ok(Self::Id),
+            _ => Err(())
+        }
+    }
+}
+
+/// How each message is laid out in the prompt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptFormat {
+    /// One labelled line per field.
+    Verbose,
+    /// A single `PROMPT_DELIMITER`-separated line, which takes far fewer tokens.
+    Compact
+}
+
+impl FromStr for PromptFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "verbose" => Ok(Self::Verbose),
+            "compact" => Ok(Self::Compact),
+            _ => Err(())
+        }
+    }
+}
+
+/// Whether messages from other bots are moderated. Webhook messages, including those
+/// relayed by bridges and announcement follows, arrive with a bot author and count as bots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BotMessages {
+    /// Never moderate bots, which also keeps moderation bots from looping on each other.
+    Ignore,
+    /// Moderate bots like anyone else, except those in `IGNORED_BOT_IDS`.
+    Moderate
+}
+
+impl FromStr for BotMessages {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ignore" => Ok(Self::Ignore),
+            "moderate" => Ok(Self::Moderate),
+            _ => Err(())
+        }
+    }
+}
+
+/// What happens to system messages, such as joins, boosts and pin notices, which are never validated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemMessages {
+    Ignore,
+    /// Keep them as context for the messages around them.
+    Store
+}
+
+impl FromStr for SystemMessages {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ignore" => Ok(Self::Ignore),
+            "store" => Ok(Self::Store),
+            _ => Err(())
+        }
+    }
+}
+
+/// How the context is split into chat messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContextPacking {
+    /// One user message per Discord message.
+    Separate,
+    /// The whole context in one user message, each Discord message tagged and split by `CONTEXT_SEPARATOR`.
+    /// Some models follow a single message more reliably than many consecutive user turns.
+    Single
+}
+
+impl FromStr for ContextPacking {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "separate" => Ok(Self::Separate),
+            "single" => Ok(Self::Single),
+            _ => Err(())
+        }
+    }
+}
+
+/// What to do with a message when its channel has fewer than `MIN_CONTEXT` stored messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SparseContextAction {
+    /// Wait until enough context has been collected.
+    Skip,
+    /// Validate anyway, telling the model how little context there is.
+    Standalone
+}
+
+impl SparseContextAction {
+    /// Whether a message waits for more context when its channel has `context` messages, itself
+    /// included, under `MIN_CONTEXT=min_context`. Messages that are `always_checked` never wait.
+    pub fn skips(self, context: usize, min_context: usize, always_checked: bool) -> bool {
+        self == Self::Skip && context < min_context && !always_checked
+    }
+}
+
+impl FromStr for SparseContextAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "standalone" => Ok(Self::Standalone),
+            _ => Err(())
+        }
+    }
+}
+
+/// What happens to links whose domain is not in `ALLOWED_DOMAINS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownDomainAction {
+    /// Flag the message without asking the model.
+    Flag,
+    /// Ask the model, pointing out the unknown domain.
+    Escalate
+}
+
+impl FromStr for UnknownDomainAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "flag" => Ok(Self::Flag),
+            "escalate" => Ok(Self::Escalate),
+            _ => Err(())
+        }
+    }
+}
+
+/// What happens to a message matching a `PREFILTERS` pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrefilterAction {
+    /// Flag the message without asking the model.
+    Flag,
+    /// Always ask the model, pointing out the match.
+    Escalate
+}
+
+impl FromStr for PrefilterAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "flag" => Ok(Self::Flag),
+            "escalate" => Ok(Self::Escalate),
+            _ => Err(())
+        }
+    }
+}
+
+/// A `PREFILTERS` line, `action:pattern`, compiled once at startup.
+#[derive(Clone, Debug)]
+pub struct Prefilter {
+    pub action: PrefilterAction,
+    pub pattern: Regex
+}
+
+/// A `VALIDATION_FIELDS` entry, `field=name`, asking the model for `name` in place of the verdict's `field`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldName {
+    pub field: String,
+    pub name: String
+}
+
+impl FromStr for FieldName {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, name) = s.split_once('=').ok_or(())?;
+        let (field, name) = (field.trim(), name.trim());
+        if !["user_id", "reason", "confidence", "severity"].contains(&field) || name.is_empty() {
+            return Err(());
+        }
+        Ok(Self { field: field.into(), name: name.into() })
+    }
+}
+
+/// A named moderation profile from `PROFILES`, picked per channel with `/profile`.
+/// `PROFILE_<NAME>_PROMPT` is added to the system prompt, and `PROFILE_<NAME>_TEMPERATURE`
+/// and `PROFILE_<NAME>_MAX_TOKENS` override the sampling parameters.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    pub prompt: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u64>
+}
+
+impl Profile {
+    fn from_env(name: String) -> Result<Self, ConfigError> {
+        let prefix = format!("PROFILE_{}", name.to_ascii_uppercase().replace(['-', ' '], "_"));
+        let var = |suffix: &str| std::env::var(format!("{prefix}_{suffix}")).ok();
+        let invalid = |value: String| ConfigError::Invalid { key: "PROFILES", value };
+        Ok(Self {
+            prompt: var("PROMPT"),
+            temperature: var("TEMPERATURE").map(|x| x.parse().map_err(|_| invalid(x))).transpose()?,
+            max_tokens: var("MAX_TOKENS").map(|x| x.parse().map_err(|_| invalid(x))).transpose()?,
+            name
+        })
+    }
+
+    pub fn sampling(&self, base: Sampling) -> Sampling {
+        Sampling {
+            temperature: self.temperature.unwrap_or(base.temperature),
+            max_tokens: self.max_tokens.unwrap_or(base.max_tokens),
+            ..base
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Missing(&'static str),
+    /// Set, but to nothing but whitespace.
+    Empty(&'static str),
+    Invalid { key: &'static str, value: String }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(key) => write!(f, "missing required environment variable {key}"),
+            Self::Empty(key) => write!(f, "environment variable {key} is empty"),
+            Self::Invalid { key, value } => write!(f, "invalid value {value:?} for {key}")
+        }
+    }
+}
+
+/// Sampling parameters sent with every completion request.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Sampling {
+    pub max_tokens: u64,
+    pub temperature: f32,
+    pub top_p: u8,
+    pub top_k: u8,
+    pub presence_penalty: u8,
+    pub frequency_penalty: u8,
+    /// Makes sampling reproducible when set, for debugging decisions.
+    pub seed: Option<u64>,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            max_tokens: 4096,
+            temperature: 0.6,
+            top_p: 1,
+            top_k: 40,
+            presence_penalty: 0,
+            frequency_penalty: 0,
+            seed: None
+        }
+    }
+}
+
+impl Sampling {
+    fn from_env() -> Result<Self, ConfigError> {
+        let default = Self::default();
+        Ok(Self {
+            max_tokens: optional("MAX_TOKENS")?.unwrap_or(default.max_tokens),
+            temperature: optional("TEMPERATURE")?.unwrap_or(default.temperature),
+            top_p: optional("TOP_P")?.unwrap_or(default.top_p),
+            top_k: optional("TOP_K")?.unwrap_or(default.top_k),
+            presence_penalty: optional("PRESENCE_PENALTY")?.unwrap_or(default.presence_penalty),
+            frequency_penalty: optional("FREQUENCY_PENALTY")?.unwrap_or(default.frequency_penalty),
+            seed: optional("SEED")?
+        })
+    }
+}
+
+/// Everything the bot reads from the environment, loaded once at startup.
+pub struct Config {
+    pub api_key: String,
+    pub application_id: u64,
+    /// Users allowed to run owner-only commands.
+    pub owner_ids: Vec<u64>,
+    /// `SYSTEM_PROMPT`, or `SYSTEM_PROMPT_<LANGUAGE>` when `PROMPT_LANGUAGE` has a localized prompt.
+    pub system_prompt: String,
+    /// Language the model is told to write its reasons in.
+    pub prompt_language: Option<String>,
+    /// Instructions placed before and after `SYSTEM_PROMPT`, so they survive edits to it.
+    pub prompt_prefix: Option<String>,
+    pub prompt_suffix: Option<String>,
+    pub prompt_format: PromptFormat,
+    /// Verdict fields the model is asked for under another name.
+    pub validation_fields: Vec<FieldName>,
+    /// Profiles channels can be set to with `/profile`.
+    pub profiles: Vec<Profile>,
+    /// Replace user ids in prompts with pseudonyms like "User A", mapped back to the real user for enforcement.
+    pub anonymize_users: bool,
+    pub prompt_delimiter: String,
+    /// Role of the message carrying the system prompt, for providers that name it differently.
+    pub instruction_role: String,
+    /// Put the system prompt at the top of the first user message instead of a message of its own.
+    pub fold_instructions: bool,
+    pub context_packing: ContextPacking,
+    pub context_separator: String,
+    pub context_size: usize,
+    pub context_eviction: EvictionStrategy,
+    pub context_ttl_secs: Option<i64>,
+    /// Prepend recent messages from a thread's parent channel to its context.
+    pub include_thread_parent: bool,
+    pub context_order: ContextOrder,
+    /// Stored messages, including the new one, a channel needs before it is validated normally.
+    pub min_context: usize,
+    pub sparse_context_action: SparseContextAction,
+    /// Summarize older context with a separate model call once a channel's context
+    /// exceeds `SUMMARIZE_THRESHOLD` messages, keeping the newest `SUMMARY_RAW_MESSAGES` verbatim.
+    pub summarize_context: bool,
+    pub summarize_threshold: usize,
+    pub summary_raw_messages: usize,
+    /// A channel's cached summary is regenerated once it is older than this.
+    pub summary_refresh_secs: u64,
+    /// Stored content longer than this many characters is truncated. Unlimited when unset.
+    pub max_stored_content_len: Option<usize>,
+    /// Messages older than this many seconds when they arrive, such as gateway replays
+    /// after a reconnect, are not validated. Off when unset.
+    pub max_message_age_secs: Option<i64>,
+    /// Keep stale messages as context for later validations.
+    pub store_stale_messages: bool,
+    /// Messages mentioning this many users and roles, or @everyone or @here, are flagged
+    /// without asking the model. Authors allowed to mention everyone are exempt. Off when unset.
+    pub mass_mention_threshold: Option<usize>,
+    /// Words flagged while the provider is unavailable, and in names under `MODERATE_NICKNAMES`.
+    pub blocked_words: Vec<String>,
+    /// Links or mentions in one message that are flagged while the provider is unavailable.
+    pub degraded_spam_threshold: usize,
+    /// Reactions from one user within the window that count as spam. Detection is off when unset.
+    pub reaction_spam_threshold: Option<usize>,
+    pub reaction_spam_window_secs: u64,
+    /// Identical messages from this many authors within the window are flagged together
+    /// as a spam wave, without asking the model. Detection is off when unset.
+    pub duplicate_authors: Option<usize>,
+    pub duplicate_window_secs: i64,
+    /// After acting on a user, further flags for them within this many seconds are only logged.
+    pub flag_cooldown_secs: u64,
+    /// Members who joined less than this many seconds ago are moderated strictly
+    /// and always checked by the model. Off when unset.
+    pub new_member_grace_secs: Option<i64>,
+    /// Check members' nicknames and usernames when they change, resetting offensive nicknames.
+    /// Needs the privileged server members intent.
+    pub moderate_nicknames: bool,
+    pub enforcement_mode: EnforcementMode,
+    /// Per-severity overrides of `ENFORCEMENT_MODE`, none of which overlap.
+    pub severity_actions: Vec<SeverityTier>,
+    pub quarantine_channel_id: Option<u64>,
+    /// Warning counts at which flagged users are timed out or kicked. Each tier is off when unset.
+    pub warn_timeout_at: Option<usize>,
+    pub warn_kick_at: Option<usize>,
+    pub timeout_secs: i64,
+    /// Where warnings and other persistent state are saved.
+    pub state_path: String,
+    /// Database messages that were dropped are appended here as JSON lines. Only logged when unset.
+    pub dead_letter_path: Option<String>,
+    /// Requests the database thread can fall behind by before messages that need no reply are dropped.
+    pub database_queue_size: usize,
+    /// Channel where mods are notified about flags, for guilds that did not pick one with `/setlogchannel`.
+    pub log_channel_id: Option<u64>,
+    /// Sandbox channel `/replay` reposts stored context into.
+    pub replay_channel_id: Option<u64>,
+    /// Every flag is also POSTed here as JSON, for external alerting.
+    pub flag_webhook_url: Option<String>,
+    /// React ✅ to messages judged fine and 🚫 to flagged ones. Noisy, meant for testing.
+    pub reaction_feedback: bool,
+    /// Reasons are cut to this many characters in DMs and mod-channel posts, since they can
+    /// quote the offending message. The audit log always gets the full reason. Unlimited when unset.
+    pub max_reason_len: Option<usize>,
+    pub uncertain_threshold: f32,
+    pub uncertain_action: UncertainAction,
+    /// Whether guilds that never used `/toggle` are moderated.
+    pub guilds_enabled_by_default: bool,
+    pub bot_messages: BotMessages,
+    pub system_messages: SystemMessages,
+    pub ignored_bot_ids: Vec<u64>,
+    /// Messages starting with this, from members with a `BYPASS_ROLE_IDS` role, are stored
+    /// without the prefix but never validated, for posting examples of rule-breaking content.
+    pub bypass_prefix: Option<String>,
+    pub bypass_role_ids: Vec<u64>,
+    /// Skip passive moderation and only validate messages through the "Moderate message" menu command,
+    /// for bots without the `MESSAGE_CONTENT` intent.
+    pub command_only: bool,
+    /// Registers the `/loadtest` command.
+    pub enable_loadtest: bool,
+    /// Registers commands for tuning prompts in-server, `/inject`, `/debug-check` and `/dump-payload`.
+    pub enable_debug_commands: bool,
+    /// Messages detected in any other language are flagged without asking the model. Off when empty.
+    pub allowed_languages: Vec<Lang>,
+    /// Messages shorter than this many characters skip language detection.
+    pub language_min_length: usize,
+    /// Endpoint that scores image attachments before the model sees the message. Off when unset.
+    pub nsfw_filter_url: Option<String>,
+    pub nsfw_threshold: f32,
+    /// Attachments with these content types are flagged without asking the model.
+    /// A `type/*` entry matches every subtype.
+    pub blocked_content_types: Vec<String>,
+    /// Links to these domains, or their subdomains, are never treated as unknown. Off when empty.
+    pub allowed_domains: Vec<String>,
+    /// Links to these domains, or their subdomains, are flagged without asking the model.
+    pub blocked_domains: Vec<String>,
+    pub unknown_domain_action: UnknownDomainAction,
+    /// Patterns checked against every message before the model sees it.
+    pub prefilters: Vec<Prefilter>,
+    pub provider: ProviderKind,
+    pub model: String,
+    /// The model's context window in tokens, used to warn about prompts that may not fit. Off when unset.
+    pub model_context_tokens: Option<usize>,
+    pub sampling: Sampling,
+    /// Where to serve Prometheus metrics. Off when unset.
+    pub metrics_addr: Option<SocketAddr>,
+    /// StatsD or DogStatsD agent to send the same metrics to over UDP. Off when unset.
+    pub statsd_addr: Option<SocketAddr>,
+    /// Upper bound on provider requests in flight at once.
+    pub max_concurrent_ai: usize,
+    /// Once either is used up, messages are only checked with heuristics until the next reset. Off when unset.
+    pub daily_request_budget: Option<u64>,
+    pub daily_token_budget: Option<u64>,
+    /// Hour of the day, in UTC, when the daily budgets reset.
+    pub budget_reset_hour: u8,
+    pub fireworks_api_key: Option<String>,
+    pub ollama_url: String,
+    /// Tried when the primary provider fails. Off when unset.
+    pub fallback_provider: Option<ProviderKind>,
+    /// Defaults to `MODEL`.
+    pub fallback_model: Option<String>,
+    /// Default to the primary's `FIREWORKS_API_KEY` and `OLLAMA_URL`.
+    pub fallback_fireworks_api_key: Option<String>,
+    pub fallback_ollama_url: Option<String>,
+    /// Idle connections kept open per host, so steady traffic skips TLS handshakes.
+    pub http_pool_max_idle_per_host: usize,
+    pub http_pool_idle_timeout_secs: u64,
+    /// TCP keep-alive interval for provider connections. Off when 0.
+    pub http_tcp_keepalive_secs: u64,
+    /// Speak HTTP/2 without negotiating it first, for plain-http providers known to support it.
+    /// HTTPS providers negotiate HTTP/2 on their own.
+    pub http2_prior_knowledge: bool,
+}
+
+impl Config {
+    /// How to enforce a flag of `severity`: its `SEVERITY_ACTIONS` tier, or `ENFORCEMENT_MODE`
+    /// without a timeout for unrated flags and severities no tier covers.
+    pub fn enforcement_for(&self, severity: Option<Severity>) -> (EnforcementMode, bool) {
+        severity.and_then(|severity| self.severity_actions.iter().find(|x| x.from <= severity && severity <= x.to))
+            .map_or((self.enforcement_mode, false), |x| (x.mode, x.timeout))
+    }
+
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let provider = optional("PROVIDER")?.unwrap_or(ProviderKind::Fireworks);
+        let enforcement_mode = optional("ENFORCEMENT_MODE")?.unwrap_or(EnforcementMode::Log);
+        let quarantine_channel_id = optional("QUARANTINE_CHANNEL_ID")?;
+        let context_eviction = optional("CONTEXT_EVICTION")?.unwrap_or(EvictionStrategy::Count);
+        let context_ttl_secs = optional("CONTEXT_TTL_SECS")?;
+        if context_eviction != EvictionStrategy::Count && context_ttl_secs.is_none() {
+            return Err(ConfigError::Missing("CONTEXT_TTL_SECS"));
+        }
+        let summarize_threshold = optional("SUMMARIZE_THRESHOLD")?.unwrap_or(30);
+        let summary_raw_messages = optional("SUMMARY_RAW_MESSAGES")?.unwrap_or(10);
+        if summary_raw_messages >= summarize_threshold {
+            return Err(ConfigError::Invalid { key: "SUMMARY_RAW_MESSAGES", value: summary_raw_messages.to_string() });
+        }
+        let log_channel_id = optional("LOG_CHANNEL_ID")?;
+        // Guilds can set their own log channel, so UNCERTAIN_ACTION=notify works without a global one.
+        let uncertain_action = optional("UNCERTAIN_ACTION")?.unwrap_or(UncertainAction::Log);
+        let severity_actions = list::<SeverityTier>("SEVERITY_ACTIONS")?;
+        for (i, tier) in severity_actions.iter().enumerate() {
+            if let Some(other) = severity_actions[..i].iter().find(|x| x.overlaps(tier)) {
+                let value = format!("{:?}-{:?} overlaps {:?}-{:?}", tier.from, tier.to, other.from, other.to);
+                return Err(ConfigError::Invalid { key: "SEVERITY_ACTIONS", value });
+            }
+        }
+        let quarantines = enforcement_mode == EnforcementMode::Quarantine || severity_actions.iter().any(|x| x.mode == EnforcementMode::Quarantine);
+        if quarantines && quarantine_channel_id.is_none() {
+            return Err(ConfigError::Missing("QUARANTINE_CHANNEL_ID"));
+        }
+        let fireworks_api_key = std::env::var("FIREWORKS_API_KEY").ok();
+        if provider == ProviderKind::Fireworks && fireworks_api_key.is_none() {
+            return Err(ConfigError::Missing("FIREWORKS_API_KEY"));
+        }
+        let fallback_provider = optional("FALLBACK_PROVIDER")?;
+        let fallback_fireworks_api_key = std::env::var("FALLBACK_FIREWORKS_API_KEY").ok();
+        if fallback_provider == Some(ProviderKind::Fireworks) && fallback_fireworks_api_key.is_none() && fireworks_api_key.is_none() {
+            return Err(ConfigError::Missing("FALLBACK_FIREWORKS_API_KEY"));
+        }
+        let prompt_language = optional::<String>("PROMPT_LANGUAGE")?;
+        let localized_prompt = prompt_language.as_ref()
+            .and_then(|x| std::env::var(format!("SYSTEM_PROMPT_{}", x.to_ascii_uppercase().replace(' ', "_"))).ok());
+        let (key, system_prompt) = match localized_prompt {
+            Some(prompt) => ("SYSTEM_PROMPT_<LANGUAGE>", prompt),
+            None => ("SYSTEM_PROMPT", required::<String>("SYSTEM_PROMPT")?)
+        };
+        // The JSON contract is appended to every prompt, so only the rules themselves are needed here.
+        if system_prompt.trim().is_empty() {
+            return Err(ConfigError::Empty(key));
+        }
+        Ok(Self {
+            api_key: required("API_KEY")?,
+            application_id: required("APPLICATION_ID")?,
+            owner_ids: list("OWNER_IDS")?,
+            system_prompt,
+            prompt_language,
+            prompt_prefix: optional("PROMPT_PREFIX")?,
+            prompt_suffix: optional("PROMPT_SUFFIX")?,
+            prompt_format: optional("PROMPT_FORMAT")?.unwrap_or(PromptFormat::Verbose),
+            validation_fields: list("VALIDATION_FIELDS")?,
+            profiles: list::<String>("PROFILES")?.into_iter().map(Profile::from_env).collect::<Result<_, _>>()?,
+            anonymize_users: optional("ANONYMIZE_USERS")?.unwrap_or(false),
+            prompt_delimiter: optional("PROMPT_DELIMITER")?.unwrap_or_else(|| "|".into()),
+            instruction_role: optional("INSTRUCTION_ROLE")?.unwrap_or_else(|| "system".into()),
+            fold_instructions: optional("FOLD_INSTRUCTIONS")?.unwrap_or(false),
+            context_packing: optional("CONTEXT_PACKING")?.unwrap_or(ContextPacking::Separate),
+            context_separator: optional("CONTEXT_SEPARATOR")?.unwrap_or_else(|| "-----".into()),
+            // Like `/setcontext`, at least one message is kept, or nothing would ever be moderated.
+            context_size: match required("CONTEXT_SIZE")? {
+                0 => return Err(ConfigError::Invalid { key: "CONTEXT_SIZE", value: "0".into() }),
+                size => size
+            },
+            context_eviction,
+            context_ttl_secs,
+            include_thread_parent: optional("INCLUDE_THREAD_PARENT")?.unwrap_or(false),
+            context_order: optional("CONTEXT_ORDER")?.unwrap_or(ContextOrder::Timestamp),
+            min_context: optional("MIN_CONTEXT")?.unwrap_or(1),
+            sparse_context_action: optional("SPARSE_CONTEXT_ACTION")?.unwrap_or(SparseContextAction::Standalone),
+            summarize_context: optional("SUMMARIZE_CONTEXT")?.unwrap_or(false),
+            summarize_threshold,
+            summary_raw_messages,
+            summary_refresh_secs: optional("SUMMARY_REFRESH_SECS")?.unwrap_or(300),
+            max_stored_content_len: optional("MAX_STORED_CONTENT_LEN")?,
+            max_message_age_secs: optional("MAX_MESSAGE_AGE_SECS")?,
+            store_stale_messages: optional("STORE_STALE_MESSAGES")?.unwrap_or(true),
+            mass_mention_threshold: optional("MASS_MENTION_THRESHOLD")?,
+            blocked_words: list::<String>("BLOCKED_WORDS")?.into_iter().map(|x| x.to_lowercase()).collect(),
+            degraded_spam_threshold: optional("DEGRADED_SPAM_THRESHOLD")?.unwrap_or(5),
+            reaction_spam_threshold: optional("REACTION_SPAM_THRESHOLD")?,
+            reaction_spam_window_secs: optional("REACTION_SPAM_WINDOW_SECS")?.unwrap_or(10),
+            duplicate_authors: optional("DUPLICATE_AUTHORS")?,
+            duplicate_window_secs: optional("DUPLICATE_WINDOW_SECS")?.unwrap_or(60),
+            flag_cooldown_secs: optional("FLAG_COOLDOWN_SECS")?.unwrap_or(0),
+            new_member_grace_secs: optional("NEW_MEMBER_GRACE_SECS")?,
+            moderate_nicknames: optional("MODERATE_NICKNAMES")?.unwrap_or(false),
+            enforcement_mode,
+            severity_actions,
+            quarantine_channel_id,
+            warn_timeout_at: optional("WARN_TIMEOUT_AT")?,
+            warn_kick_at: optional("WARN_KICK_AT")?,
+            timeout_secs: optional("TIMEOUT_SECS")?.unwrap_or(600),
+            state_path: optional("STATE_PATH")?.unwrap_or_else(|| "state.json".into()),
+            dead_letter_path: optional("DEAD_LETTER_PATH")?,
+            database_queue_size: match optional("DATABASE_QUEUE_SIZE")? {
+                Some(0) => return Err(ConfigError::Invalid { key: "DATABASE_QUEUE_SIZE", value: "0".into() }),
+                Some(size) => size,
+                None => 1024
+            },
+            log_channel_id,
+            replay_channel_id: optional("REPLAY_CHANNEL_ID")?,
+            flag_webhook_url: optional("FLAG_WEBHOOK_URL")?,
+            reaction_feedback: optional("REACTION_FEEDBACK")?.unwrap_or(false),
+            max_reason_len: optional("MAX_REASON_LEN")?,
+            uncertain_threshold: optional("UNCERTAIN_THRESHOLD")?.unwrap_or(0.5),
+            uncertain_action,
+            enable_loadtest: optional("ENABLE_LOADTEST")?.unwrap_or(false),
+            enable_debug_commands: optional("ENABLE_DEBUG_COMMANDS")?.unwrap_or(false),
+            command_only: optional("COMMAND_ONLY")?.unwrap_or(false),
+            bot_messages: optional("BOT_MESSAGES")?.unwrap_or(BotMessages::Ignore),
+            system_messages: optional("SYSTEM_MESSAGES")?.unwrap_or(SystemMessages::Ignore),
+            ignored_bot_ids: list("IGNORED_BOT_IDS")?,
+            bypass_prefix: optional::<String>("BYPASS_PREFIX")?.filter(|x| !x.is_empty()),
+            bypass_role_ids: list("BYPASS_ROLE_IDS")?,
+            guilds_enabled_by_default: optional("GUILDS_ENABLED_BY_DEFAULT")?.unwrap_or(true),
+            allowed_languages: list("ALLOWED_LANGUAGES")?,
+            language_min_length: optional("LANGUAGE_MIN_LENGTH")?.unwrap_or(20),
+            nsfw_filter_url: optional("NSFW_FILTER_URL")?,
+            nsfw_threshold: optional("NSFW_THRESHOLD")?.unwrap_or(0.8),
+            blocked_content_types: list::<String>("BLOCKED_CONTENT_TYPES")?.into_iter().map(|x| x.to_ascii_lowercase()).collect(),
+            allowed_domains: domains("ALLOWED_DOMAINS")?,
+            blocked_domains: domains("BLOCKED_DOMAINS")?,
+            unknown_domain_action: optional("UNKNOWN_DOMAIN_ACTION")?.unwrap_or(UnknownDomainAction::Escalate),
+            prefilters: prefilters("PREFILTERS")?,
+            provider,
+            model: required("MODEL")?,
+            model_context_tokens: optional("MODEL_CONTEXT_TOKENS")?,
+            sampling: Sampling::from_env()?,
+            metrics_addr: optional("METRICS_ADDR")?,
+            statsd_addr: optional("STATSD_ADDR")?,
+            max_concurrent_ai: match optional("MAX_CONCURRENT_AI")? {
+                Some(0) => return Err(ConfigError::Invalid { key: "MAX_CONCURRENT_AI", value: "0".into() }),
+                Some(permits) => permits,
+                None => 4
+            },
+            daily_request_budget: optional("DAILY_REQUEST_BUDGET")?,
+            daily_token_budget: optional("DAILY_TOKEN_BUDGET")?,
+            budget_reset_hour: match optional::<u8>("BUDGET_RESET_HOUR")? {
+                Some(hour) if hour >= 24 => return Err(ConfigError::Invalid { key: "BUDGET_RESET_HOUR", value: hour.to_string() }),
+                Some(hour) => hour,
+                None => 0
+            },
+            fireworks_api_key,
+            ollama_url: optional("OLLAMA_URL")?.unwrap_or_else(|| "http://localhost:11434".into()),
+            fallback_provider,
+            fallback_model: optional("FALLBACK_MODEL")?,
+            fallback_fireworks_api_key,
+            fallback_ollama_url: optional("FALLBACK_OLLAMA_URL")?,
+            http_pool_max_idle_per_host: optional("HTTP_POOL_MAX_IDLE_PER_HOST")?.unwrap_or(8),
+            http_pool_idle_timeout_secs: optional("HTTP_POOL_IDLE_TIMEOUT_SECS")?.unwrap_or(90),
+            http_tcp_keepalive_secs: optional("HTTP_TCP_KEEPALIVE_SECS")?.unwrap_or(60),
+            http2_prior_knowledge: optional("HTTP2_PRIOR_KNOWLEDGE")?.unwrap_or(false),
+        })
+    }
+
+    /// What the model calls the verdict's `field`, after `VALIDATION_FIELDS`.
+    pub fn validation_field<'a>(&'a self, field: &'a str) -> &'a str {
+        self.validation_fields.iter().find(|x| x.field == field).map_or(field, |x| x.name.as_str())
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|x| x.name == name)
+    }
+
+    /// The effective settings as `(name, value)` pairs, with secrets left out.
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        let sampling = &self.sampling;
+        vec![
+            ("provider", format!("{:?}", self.provider)),
+            ("model", self.model.clone()),
+            ("model_context_tokens", self.model_context_tokens.map_or("unset".into(), |x| x.to_string())),
+            ("prompt_language", self.prompt_language.clone().unwrap_or_else(|| "unset".into())),
+            ("prompt_prefix", self.prompt_prefix.is_some().to_string()),
+            ("prompt_suffix", self.prompt_suffix.is_some().to_string()),
+            ("prompt_format", format!("{:?}", self.prompt_format)),
+            ("prompt_delimiter", format!("{:?}", self.prompt_delimiter)),
+            ("instruction_role", self.instruction_role.clone()),
+            ("fold_instructions", self.fold_instructions.to_string()),
+            ("context_packing", format!("{:?}", self.context_packing)),
+            ("context_separator", format!("{:?}", self.context_separator)),
+            ("profiles", format!("{:?}", self.profiles.iter().map(|x| x.name.as_str()).collect::<Vec<&str>>())),
+            ("validation_fields", format!("{:?}", self.validation_fields.iter().map(|x| format!("{}={}", x.field, x.name)).collect::<Vec<String>>())),
+            ("anonymize_users", self.anonymize_users.to_string()),
+            ("context_size", self.context_size.to_string()),
+            ("context_eviction", format!("{:?}", self.context_eviction)),
+            ("context_ttl_secs", self.context_ttl_secs.map_or("unset".into(), |x| x.to_string())),
+            ("include_thread_parent", self.include_thread_parent.to_string()),
+            ("context_order", format!("{:?}", self.context_order)),
+            ("min_context", self.min_context.to_string()),
+            ("sparse_context_action", format!("{:?}", self.sparse_context_action)),
+            ("summarize_context", self.summarize_context.to_string()),
+            ("summarize_threshold", self.summarize_threshold.to_string()),
+            ("summary_raw_messages", self.summary_raw_messages.to_string()),
+            ("summary_refresh_secs", self.summary_refresh_secs.to_string()),
+            ("max_stored_content_len", self.max_stored_content_len.map_or("unlimited".into(), |len| len.to_string())),
+            ("max_message_age_secs", self.max_message_age_secs.map_or("off".into(), |x| x.to_string())),
+            ("store_stale_messages", self.store_stale_messages.to_string()),
+            ("mass_mention_threshold", self.mass_mention_threshold.map_or("off".into(), |x| x.to_string())),
+            ("blocked_words", self.blocked_words.len().to_string()),
+            ("degraded_spam_threshold", self.degraded_spam_threshold.to_string()),
+            ("reaction_spam_threshold", self.reaction_spam_threshold.map_or("off".into(), |x| x.to_string())),
+            ("reaction_spam_window_secs", self.reaction_spam_window_secs.to_string()),
+            ("duplicate_authors", self.duplicate_authors.map_or("off".into(), |x| x.to_string())),
+            ("duplicate_window_secs", self.duplicate_window_secs.to_string()),
+            ("flag_cooldown_secs", self.flag_cooldown_secs.to_string()),
+            ("new_member_grace_secs", self.new_member_grace_secs.map_or("off".into(), |x| x.to_string())),
+            ("moderate_nicknames", self.moderate_nicknames.to_string()),
+            ("enforcement_mode", format!("{:?}", self.enforcement_mode)),
+            ("severity_actions", format!("{:?}", self.severity_actions.iter().map(|x| format!("{:?}-{:?}={:?}{}", x.from, x.to, x.mode, if x.timeout { "+timeout" } else { "" })).collect::<Vec<String>>())),
+            ("quarantine_channel_id", self.quarantine_channel_id.map_or("unset".into(), |x| x.to_string())),
+            ("warn_timeout_at", self.warn_timeout_at.map_or("off".into(), |x| x.to_string())),
+            ("warn_kick_at", self.warn_kick_at.map_or("off".into(), |x| x.to_string())),
+            ("timeout_secs", self.timeout_secs.to_string()),
+            ("state_path", self.state_path.clone()),
+            ("dead_letter_path", self.dead_letter_path.clone().unwrap_or_else(|| "unset".into())),
+            ("database_queue_size", self.database_queue_size.to_string()),
+            ("log_channel_id", self.log_channel_id.map_or("unset".into(), |x| x.to_string())),
+            ("replay_channel_id", self.replay_channel_id.map_or("unset".into(), |x| x.to_string())),
+            ("flag_webhook", self.flag_webhook_url.is_some().to_string()),
+            ("reaction_feedback", self.reaction_feedback.to_string()),
+            ("max_reason_len", self.max_reason_len.map_or("unlimited".into(), |len| len.to_string())),
+            ("uncertain_threshold", self.uncertain_threshold.to_string()),
+            ("uncertain_action", format!("{:?}", self.uncertain_action)),
+            ("owner_ids", format!("{:?}", self.owner_ids)),
+            ("enable_loadtest", self.enable_loadtest.to_string()),
+            ("enable_debug_commands", self.enable_debug_commands.to_string()),
+            ("command_only", self.command_only.to_string()),
+            ("bot_messages", format!("{:?}", self.bot_messages)),
+            ("system_messages", format!("{:?}", self.system_messages)),
+            ("ignored_bot_ids", format!("{:?}", self.ignored_bot_ids)),
+            ("bypass_prefix", format!("{:?}", self.bypass_prefix)),
+            ("bypass_role_ids", format!("{:?}", self.bypass_role_ids)),
+            ("guilds_enabled_by_default", self.guilds_enabled_by_default.to_string()),
+            ("metrics_addr", self.metrics_addr.map_or("unset".into(), |x| x.to_string())),
+            ("statsd_addr", self.statsd_addr.map_or("unset".into(), |x| x.to_string())),
+            ("max_concurrent_ai", self.max_concurrent_ai.to_string()),
+            ("daily_request_budget", self.daily_request_budget.map_or("off".into(), |x| x.to_string())),
+            ("daily_token_budget", self.daily_token_budget.map_or("off".into(), |x| x.to_string())),
+            ("budget_reset_hour", self.budget_reset_hour.to_string()),
+            ("http_pool_max_idle_per_host", self.http_pool_max_idle_per_host.to_string()),
+            ("http_pool_idle_timeout_secs", self.http_pool_idle_timeout_secs.to_string()),
+            ("http_tcp_keepalive_secs", self.http_tcp_keepalive_secs.to_string()),
+            ("http2_prior_knowledge", self.http2_prior_knowledge.to_string()),
+            ("allowed_languages", format!("{:?}", self.allowed_languages.iter().map(|x| x.code()).collect::<Vec<&str>>())),
+            ("language_min_length", self.language_min_length.to_string()),
+            ("nsfw_filter", self.nsfw_filter_url.is_some().to_string()),
+            ("nsfw_threshold", self.nsfw_threshold.to_string()),
+            ("blocked_content_types", format!("{:?}", self.blocked_content_types)),
+            ("allowed_domains", format!("{:?}", self.allowed_domains)),
+            ("blocked_domains", format!("{:?}", self.blocked_domains)),
+            ("unknown_domain_action", format!("{:?}", self.unknown_domain_action)),
+            ("prefilters", format!("{:?}", self.prefilters.iter().map(|x| format!("{:?}:{}", x.action, x.pattern)).collect::<Vec<String>>())),
+            ("max_tokens", sampling.max_tokens.to_string()),
+            ("temperature", sampling.temperature.to_string()),
+            ("top_p", sampling.top_p.to_string()),
+            ("top_k", sampling.top_k.to_string()),
+            ("presence_penalty", sampling.presence_penalty.to_string()),
+            ("frequency_penalty", sampling.frequency_penalty.to_string()),
+            ("seed", sampling.seed.map_or("unset".into(), |x| x.to_string())),
+            ("ollama_url", self.ollama_url.clone()),
+            ("fallback_provider", self.fallback_provider.map_or("off".into(), |x| format!("{x:?}"))),
+            ("fallback_model", self.fallback_model.clone().unwrap_or_else(|| "unset".into())),
+            ("fallback_ollama_url", self.fallback_ollama_url.clone().unwrap_or_else(|| "unset".into())),
+        ]
+    }
+}
+
+/// Makes `config` available through [`get`]. Must be called once, before the client starts.
+pub fn init(config: Config) {
+    if CONFIG.set(config).is_err() {
+        panic!("config initialized twice");
+    }
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("config not initialized")
+}
+
+/// Initializes the config for unit tests from a fixed environment, once per test binary.
+/// Settings the tests rely on are pinned so a developer's own environment can't change them.
+#[cfg(test)]
+pub fn init_for_tests() -> &'static Config {
+    CONFIG.get_or_init(|| {
+        let state_path = std::env::temp_dir().join(format!("discord_bot_test_state_{}.json", std::process::id()));
+        let vars = [
+            ("API_KEY", "test"),
+            ("APPLICATION_ID", "1"),
+            ("SYSTEM_PROMPT", "Flag insults."),
+            ("CONTEXT_SIZE", "10"),
+            ("MODEL", "test-model"),
+            ("PROVIDER", "fireworks"),
+            ("FIREWORKS_API_KEY", "test"),
+            ("PROMPT_FORMAT", "verbose"),
+            ("INSTRUCTION_ROLE", "system"),
+            ("FOLD_INSTRUCTIONS", "false"),
+            ("CONTEXT_PACKING", "separate"),
+            ("CONTEXT_ORDER", "timestamp"),
+            ("MIN_CONTEXT", "4"),
+            ("SPARSE_CONTEXT_ACTION", "standalone"),
+            ("SUMMARIZE_CONTEXT", "false"),
+            ("ANONYMIZE_USERS", "false"),
+            ("STATE_PATH", state_path.to_str().expect("the temp dir is valid unicode"))
+        ];
+        for (key, value) in vars {
+            std::env::set_var(key, value);
+        }
+        for key in ["PROMPT_LANGUAGE", "PROMPT_PREFIX", "PROMPT_SUFFIX", "PROFILES", "VALIDATION_FIELDS", "DEAD_LETTER_PATH"] {
+            std::env::remove_var(key);
+        }
+        Config::from_env().expect("the test environment is a valid config")
+    })
+}
+
+fn required<T: FromStr>(key: &'static str) -> Result<T, ConfigError> {
+    optional(key)?.ok_or(ConfigError::Missing(key))
+}
+
+/// Parses a comma-separated list, empty when unset.
+fn list<T: FromStr>(key: &'static str) -> Result<Vec<T>, ConfigError> {
+    let Ok(value) = std::env::var(key) else {
+        return Ok(Vec::new());
+    };
+    value.split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse::<T>().map_err(|_| ConfigError::Invalid { key, value: value.clone() }))
+        .collect()
+}
+
+fn domains(key: &'static str) -> Result<Vec<String>, ConfigError> {
+    Ok(list::<String>(key)?.into_iter().map(|x| x.to_ascii_lowercase()).collect())
+}
+
+/// Parses one `action:pattern` per line, so patterns are free to contain commas.
+/// An invalid pattern fails startup with the regex error instead of never matching.
+fn prefilters(key: &'static str) -> Result<Vec<Prefilter>, ConfigError> {
+    let Ok(value) = std::env::var(key) else {
+        return Ok(Vec::new());
+    };
+    value.lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|line| {
+            let invalid = |why: String| ConfigError::Invalid { key, value: format!("{line} ({why})") };
+            let (action, pattern) = line.split_once(':').ok_or_else(|| invalid("expected action:pattern".into()))?;
+            let action = action.parse().map_err(|_| invalid("the action must be flag or escalate".into()))?;
+            let pattern = Regex::new(pattern).map_err(|why| invalid(why.to_string()))?;
+            Ok(Prefilter { action, pattern })
+        })
+        .collect()
+}
+
+fn optional<T: FromStr>(key: &'static str) -> Result<Option<T>, ConfigError> {
+    match std::env::var(key) {
+        Ok(value) => value.parse::<T>()
+            .map(Some)
+            .map_err(|_| ConfigError::Invalid { key, value }),
+        Err(_) => Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_waits_below_min_context() {
+        let min_context = 4;
+        for context in [0, 1, min_context - 1] {
+            assert!(SparseContextAction::Skip.skips(context, min_context, false));
+        }
+        assert!(!SparseContextAction::Skip.skips(min_context, min_context, false));
+    }
+
+    #[test]
+    fn always_checked_messages_never_wait() {
+        for context in [0, 1, 3] {
+            assert!(!SparseContextAction::Skip.skips(context, 4, true));
+        }
+    }
+
+    #[test]
+    fn standalone_never_waits() {
+        for context in [0, 1, 3] {
+            assert!(!SparseContextAction::Standalone.skips(context, 4, false));
+        }
+    }
+}
@@ -1 +1,3 @@
+pub mod connection;
 pub mod definitions;
+pub mod state;
@@ -0,0 +1,132 @@
+use std::{collections::HashMap, fs::OpenOptions, io::Write, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex, RwLock}, time::Instant};
+
+use crossbeam::channel::{Receiver, Sender, TrySendError};
+use serde_json::json;
+use serenity::all::Timestamp;
+use tracing::error;
+use crate::{config, metrics};
+use super::definitions::{Database, DatabaseMessage, DatabaseResponse};
+
+/// The handles to the database thread, swapped for fresh ones when the thread dies.
+/// Stored messages are lost on a restart; the persistent state is reloaded from `STATE_PATH`,
+/// and the context size set with `/setcontext` carries over.
+///
+/// There is one database thread per process, and it owns every channel's context and the
+/// persistent state. Clones share that thread and its handles, so every shard, task and
+/// handler that holds one sees the same context, and a restart through any clone repoints
+/// all of them. Requests from all clones are handled one at a time, in the order they arrive.
+#[derive(Clone)]
+pub struct DatabaseConnection {
+    handles: Arc<RwLock<(Sender<DatabaseMessage>, Receiver<DatabaseResponse>)>>,
+    /// Serializes request/response pairs so concurrent callers can't read each other's replies.
+    lock: Arc<Mutex<()>>,
+    /// Starts as `CONTEXT_SIZE`, changed at runtime with `/setcontext`, so restarts keep it.
+    context_size: Arc<AtomicUsize>
+}
+
+impl DatabaseConnection {
+    pub fn new() -> Self {
+        let context_size = config::get().context_size;
+        Self {
+            handles: Arc::new(RwLock::new(Database::new(context_size))),
+            lock: Arc::new(Mutex::new(())),
+            context_size: Arc::new(AtomicUsize::new(context_size))
+        }
+    }
+
+    /// Starts a new database thread and points every later call at it.
+    pub fn restart(&self) {
+        *self.handles.write().unwrap() = Database::new(self.context_size());
+    }
+
+    pub fn context_size(&self) -> usize {
+        self.context_size.load(Ordering::Relaxed)
+    }
+
+    /// Changes how many messages are kept per channel, in this database thread and the ones that replace it.
+    pub fn set_context_size(&self, context_size: usize) {
+        self.context_size.store(context_size, Ordering::Relaxed);
+        self.send(DatabaseMessage::SetContextSize(context_size));
+    }
+
+    /// Shuts the database thread down once it has handled everything sent before, and starts
+    /// a fresh one holding the same messages, trimmed to the current context size. The persistent
+    /// state is saved by the old thread and reloaded by the new one. A thread that is already gone
+    /// is replaced with an empty one.
+    pub fn drain_and_restart(&self) {
+        let _guard = self.lock.lock().unwrap();
+        let mut handles = self.handles.write().unwrap();
+        let (sender, receiver) = &*handles;
+        let messages = match sender.send(DatabaseMessage::Shutdown).ok().and_then(|_| receiver.recv().ok()) {
+            Some(DatabaseResponse::Drained(messages)) => messages,
+            _ => {
+                error!("the database thread is gone, restarting it without its messages");
+                HashMap::new()
+            }
+        };
+        *handles = Database::with_messages(messages, self.context_size());
+    }
+
+    /// Sends a message that has no reply without waiting for the database to catch up. It is dropped
+    /// to the dead-letter log when `DATABASE_QUEUE_SIZE` requests are already waiting, and when the
+    /// database is gone even after restarting it once.
+    pub fn send(&self, message: DatabaseMessage) {
+        let result = self.handles.read().unwrap().0.try_send(message);
+        let message = match result {
+            Ok(()) => return,
+            Err(TrySendError::Full(message)) => return dead_letter(&message),
+            Err(TrySendError::Disconnected(message)) => message
+        };
+        error!("the database thread is gone, restarting it");
+        self.restart();
+        if let Err(failed) = self.handles.read().unwrap().0.try_send(message) {
+            dead_letter(&failed.into_inner());
+        }
+    }
+
+    /// Sends a query and waits for its reply, restarting the database once if it is gone.
+    /// A query the fresh database doesn't answer either is dropped to the dead-letter log.
+    pub fn query(&self, message: DatabaseMessage) -> Option<DatabaseResponse> {
+        let _guard = self.lock.lock().unwrap();
+        let started = Instant::now();
+        if let Some(response) = self.round_trip(message.clone()) {
+            metrics::get().database_latency.record(started.elapsed());
+            return Some(response);
+        }
+        error!("the database thread is gone, restarting it");
+        self.restart();
+        let response = self.round_trip(message.clone());
+        if response.is_none() {
+            dead_letter(&message);
+        }
+        response
+    }
+
+    fn round_trip(&self, message: DatabaseMessage) -> Option<DatabaseResponse> {
+        let (sender, receiver) = &*self.handles.read().unwrap();
+        sender.send(message).ok()?;
+        receiver.recv().ok()
+    }
+}
+
+/// Reports a message the database never received, and appends it to `DEAD_LETTER_PATH`
+/// so what was lost can be reviewed later.
+fn dead_letter(message: &DatabaseMessage) {
+    let summary = message.summary();
+    error!("dropped a database message: {summary}");
+    let Some(path) = &config::get().dead_letter_path else {
+        return;
+    };
+    let line = json!({ "timestamp": Timestamp::now().unix_timestamp(), "message": summary });
+    let result = OpenOptions::new().create(true).append(true).open(path)
+        .and_then(|mut file| writeln!(file, "{line}"));
+    if let Err(why) = result {
+        error!("failed to write to the dead-letter log {path}: {why}");
+    }
+}
+
+impl Default for DatabaseConnection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
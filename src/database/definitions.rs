@@ -1,20 +1,55 @@
-use std::collections::VecDeque;
+use std::{collections::{HashMap, HashSet, VecDeque}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 
-use crossbeam::channel::{unbounded, Sender, Receiver};
-use crate::PartialMessage;
+use crossbeam::channel::{bounded, unbounded, Sender, Receiver, RecvTimeoutError};
+use tracing::debug;
+use crate::{config::{self, EvictionStrategy}, heuristics, PartialMessage, ValidationStatus};
+use super::state::{GuildSettings, PersistentState, PromptSnapshot, Warning};
+
+/// How often age-based eviction runs when no messages arrive.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct Database {
-    messages: VecDeque<PartialMessage>,
-    sender: Sender<Vec<PartialMessage>>,
+    messages: HashMap<u64, VecDeque<PartialMessage>>,
+    state: PersistentState,
+    /// Starts as `CONTEXT_SIZE`, changed at runtime with `/setcontext`.
+    context_size: usize,
+    /// The newest message timestamp seen per channel, kept after its messages are evicted.
+    last_seen: HashMap<u64, i64>,
+    /// When each scheduled channel was last cleared, or its schedule started.
+    last_cleared: HashMap<u64, Instant>,
+    /// Recent messages per guild and content hash, for `DUPLICATE_AUTHORS`.
+    duplicates: HashMap<(Option<u64>, u64), VecDeque<Duplicate>>,
+    sender: Sender<DatabaseResponse>,
     receiver: Receiver<DatabaseMessage>,
 }
 
 impl Database {
-    pub fn new() -> (Sender<DatabaseMessage>, Receiver<Vec<PartialMessage>>) {
-        let (db_message_sender, db_message_receiver) = unbounded();
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(context_size: usize) -> (Sender<DatabaseMessage>, Receiver<DatabaseResponse>) {
+        Self::with_messages(HashMap::new(), context_size)
+    }
+
+    /// Starts a database thread keeping `context_size` messages per channel, holding `messages` trimmed to it.
+    pub fn with_messages(mut messages: HashMap<u64, VecDeque<PartialMessage>>, context_size: usize) -> (Sender<DatabaseMessage>, Receiver<DatabaseResponse>) {
+        let config = config::get();
+        if config.context_eviction != EvictionStrategy::Age {
+            for messages in messages.values_mut() {
+                let excess = messages.len().saturating_sub(context_size);
+                messages.drain(..excess);
+            }
+        }
+        let last_seen = messages.iter()
+            .filter_map(|(channel_id, messages)| Some((*channel_id, messages.iter().map(|x| x.timestamp).max()?)))
+            .collect();
+        let (db_message_sender, db_message_receiver) = bounded(config.database_queue_size);
         let (msg_sender, msg_receiver) = unbounded();
         let mut database = Self {
-            messages: VecDeque::new(),
+            messages,
+            state: PersistentState::load(&config::get().state_path),
+            context_size,
+            last_seen,
+            last_cleared: HashMap::new(),
+            duplicates: HashMap::new(),
             sender: msg_sender,
             receiver: db_message_receiver
         };
@@ -25,33 +60,434 @@ impl Database {
     }
 
     fn update(&mut self) {
-        while let Ok(message) = self.receiver.recv() {
+        loop {
+            let message = match self.receiver.recv_timeout(EVICTION_INTERVAL) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.evict_expired();
+                    self.clear_scheduled();
+                    self.evict_duplicates();
+                    continue;
+                },
+                Err(RecvTimeoutError::Disconnected) => break
+            };
+            if let DatabaseMessage::Shutdown = message {
+                self.save();
+                let _ = self.sender.send(DatabaseResponse::Drained(std::mem::take(&mut self.messages)));
+                debug!("the database thread shut down");
+                break;
+            }
             match message {
                 DatabaseMessage::InsertMessage(message) => {
-                    let context_size = std::env::var("CONTEXT_SIZE").unwrap().parse::<usize>().unwrap();
-                    if self.messages.len() >= context_size {
-                        self.messages.pop_front();
+                    self.insert(message);
+                },
+                DatabaseMessage::InsertAndGetLatest { message, n_latest } => {
+                    let channel_id = message.channel_id;
+                    // Only messages on their way to moderation count toward a wave, since
+                    // nothing acts on one completed by a plain insert.
+                    let wave = self.track_duplicate(&message);
+                    self.insert(message);
+                    let _ = self.sender.send(DatabaseResponse::Inserted { latest: self.latest(channel_id, n_latest), wave });
+                },
+                DatabaseMessage::Backfill { channel_id, messages } => self.backfill(channel_id, messages),
+                DatabaseMessage::GetLatest { channel_id, n_latest } => {
+                    let _ = self.sender.send(DatabaseResponse::Messages(self.latest(channel_id, n_latest)));
+                },
+                DatabaseMessage::GetMessage { channel_id, id } => {
+                    let message = self.messages.get(&channel_id)
+                        .and_then(|messages| messages.iter().find(|x| x.id == id))
+                        .cloned();
+                    let _ = self.sender.send(DatabaseResponse::Message(message.map(Box::new)));
+                },
+                DatabaseMessage::EditMessage { channel_id, id, content } => {
+                    let message = self.messages.get_mut(&channel_id)
+                        .and_then(|messages| messages.iter_mut().find(|x| x.id == id))
+                        .filter(|x| x.content != content)
+                        .map(|message| {
+                            message.previous_content = Some(std::mem::replace(&mut message.content, content));
+                            // The new content has not been judged yet.
+                            message.status = ValidationStatus::NotValidated;
+                            message.clone()
+                        });
+                    let _ = self.sender.send(DatabaseResponse::Message(message.map(Box::new)));
+                },
+                DatabaseMessage::GetByStatus { channel_id, status } => {
+                    let messages = self.messages.get(&channel_id)
+                        .map(|messages| messages.iter().filter(|x| x.status == status).cloned().collect())
+                        .unwrap_or_default();
+                    let _ = self.sender.send(DatabaseResponse::Messages(messages));
+                },
+                DatabaseMessage::SetClearSchedule { channel_id, interval_secs } => {
+                    match interval_secs {
+                        Some(interval_secs) => self.state.clear_schedules.insert(channel_id, interval_secs),
+                        None => self.state.clear_schedules.remove(&channel_id)
+                    };
+                    self.last_cleared.insert(channel_id, Instant::now());
+                    self.save();
+                },
+                DatabaseMessage::ValidateEntries { channel_id, ids } => self.validate(channel_id, &ids),
+                DatabaseMessage::Activity => {
+                    let activity = self.last_seen.iter().map(|(channel_id, timestamp)| (*channel_id, *timestamp)).collect();
+                    let _ = self.sender.send(DatabaseResponse::Activity(activity));
+                },
+                DatabaseMessage::SetContextSize(context_size) => {
+                    self.context_size = context_size;
+                    if config::get().context_eviction != EvictionStrategy::Age {
+                        for messages in self.messages.values_mut() {
+                            let excess = messages.len().saturating_sub(context_size);
+                            messages.drain(..excess);
+                        }
+                        self.messages.retain(|_, messages| !messages.is_empty());
                     }
-                    self.messages.push_back(message);
-                    println!("{:?}", self.messages);
-                },
-                DatabaseMessage::GetLatest(n_latest) => {
-                    let start = std::cmp::max(n_latest as usize, self.messages.len()) - (n_latest as usize);
-                    let slice = self.messages.as_slices().0[start..].to_vec();
-                    let _ = self.sender.send(slice);
-                },
-                DatabaseMessage::ValidateEntries(n_latest) => {
-                    let start = std::cmp::max(n_latest as usize, self.messages.len()) - (n_latest as usize);
-                    let slice = &mut self.messages.as_mut_slices().0[start..];
-                    slice.iter_mut().for_each(|x| x.status = "validated".into());
-                }
+                },
+                DatabaseMessage::Stats => {
+                    let channels = self.messages.iter()
+                        .map(|(channel_id, messages)| ChannelStats {
+                            channel_id: *channel_id,
+                            messages: messages.len(),
+                            oldest: messages.iter().map(|x| x.timestamp).min().unwrap_or_default(),
+                            newest: messages.iter().map(|x| x.timestamp).max().unwrap_or_default(),
+                            bytes: messages.iter().map(estimated_size).sum()
+                        })
+                        .collect();
+                    let _ = self.sender.send(DatabaseResponse::Stats(DatabaseStats { context_size: self.context_size, channels }));
+                },
+                DatabaseMessage::AddWarning { guild_id, user_id, warning } => {
+                    let warnings = self.state.warnings.entry(guild_id).or_default().entry(user_id).or_default();
+                    warnings.push(warning);
+                    let _ = self.sender.send(DatabaseResponse::Warnings(warnings.clone()));
+                    self.save();
+                },
+                DatabaseMessage::GetWarnings { guild_id, user_id } => {
+                    let warnings = self.state.warnings.get(&guild_id)
+                        .and_then(|users| users.get(&user_id))
+                        .cloned()
+                        .unwrap_or_default();
+                    let _ = self.sender.send(DatabaseResponse::Warnings(warnings));
+                },
+                DatabaseMessage::ClearWarnings { guild_id, user_id } => {
+                    if let Some(users) = self.state.warnings.get_mut(&guild_id) {
+                        users.remove(&user_id);
+                    }
+                    self.save();
+                },
+                DatabaseMessage::GetGuildSettings(guild_id) => {
+                    let settings = self.state.guilds.get(&guild_id).cloned().unwrap_or_default();
+                    let _ = self.sender.send(DatabaseResponse::GuildSettings(settings));
+                },
+                DatabaseMessage::SetGuildEnabled { guild_id, enabled } => {
+                    self.state.guilds.entry(guild_id).or_default().enabled = enabled;
+                    self.save();
+                },
+                DatabaseMessage::SetLogChannel { guild_id, channel_id } => {
+                    self.state.guilds.entry(guild_id).or_default().log_channel_id = channel_id;
+                    self.save();
+                },
+                DatabaseMessage::GetChannelProfiles => {
+                    let _ = self.sender.send(DatabaseResponse::ChannelProfiles(self.state.channel_profiles.clone()));
+                },
+                DatabaseMessage::SetChannelProfile { channel_id, profile } => {
+                    match profile {
+                        Some(profile) => self.state.channel_profiles.insert(channel_id, profile),
+                        None => self.state.channel_profiles.remove(&channel_id)
+                    };
+                    self.save();
+                },
+                DatabaseMessage::GetPromptSnapshots => {
+                    let _ = self.sender.send(DatabaseResponse::PromptSnapshots(self.state.prompt_snapshots.clone()));
+                },
+                DatabaseMessage::SavePromptSnapshot { name, snapshot } => {
+                    self.state.prompt_snapshots.insert(name, snapshot);
+                    self.save();
+                },
+                DatabaseMessage::Shutdown => unreachable!("Shutdown ends the loop before dispatch")
             }
         }
     }
+
+    fn insert(&mut self, message: PartialMessage) {
+        self.see(&message);
+        let config = config::get();
+        let messages = self.messages.entry(message.channel_id).or_default();
+        if config.context_eviction != EvictionStrategy::Age && messages.len() >= self.context_size {
+            messages.pop_front();
+        }
+        debug!("stored message {} in {}, which now has {} messages", message.id, message.channel_id, messages.len() + 1);
+        messages.push_back(message);
+        self.evict_expired();
+        self.clear_scheduled();
+        self.evict_duplicates();
+    }
+
+    fn see(&mut self, message: &PartialMessage) {
+        let last_seen = self.last_seen.entry(message.channel_id).or_default();
+        *last_seen = message.timestamp.max(*last_seen);
+    }
+
+    /// Records `message` under its content hash. Once `DUPLICATE_AUTHORS` authors posted it
+    /// within `DUPLICATE_WINDOW_SECS`, every copy not reported yet is returned, so the copies
+    /// that started the wave are acted on along with the later ones.
+    fn track_duplicate(&mut self, message: &PartialMessage) -> Vec<PartialMessage> {
+        let config = config::get();
+        let Some(threshold) = config.duplicate_authors else {
+            return Vec::new();
+        };
+        let Some(hash) = heuristics::content_hash(&message.content) else {
+            return Vec::new();
+        };
+        let duplicates = self.duplicates.entry((message.guild_id, hash)).or_default();
+        duplicates.retain(|x| message.timestamp - x.message.timestamp <= config.duplicate_window_secs);
+        duplicates.push_back(Duplicate { message: message.clone(), reported: false });
+        let authors = duplicates.iter().map(|x| x.message.author_id).collect::<HashSet<u64>>();
+        if authors.len() < threshold {
+            return Vec::new();
+        }
+        duplicates.iter_mut()
+            .filter_map(|x| (!std::mem::replace(&mut x.reported, true)).then(|| x.message.clone()))
+            .collect()
+    }
+
+    /// Forgets content hashes that have not been seen within `DUPLICATE_WINDOW_SECS`.
+    fn evict_duplicates(&mut self) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let window = config::get().duplicate_window_secs;
+        self.duplicates.retain(|_, duplicates| {
+            duplicates.retain(|x| now - x.message.timestamp <= window);
+            !duplicates.is_empty()
+        });
+    }
+
+    /// Merges older `messages` into the channel's context in id order, skipping ones already
+    /// stored. The newest messages are kept when the merge goes over the context size.
+    fn backfill(&mut self, channel_id: u64, messages: Vec<PartialMessage>) {
+        if let Some(newest) = messages.iter().max_by_key(|x| x.timestamp) {
+            self.see(newest);
+        }
+        let stored = self.messages.entry(channel_id).or_default();
+        let before = stored.len();
+        for message in messages {
+            if !stored.iter().any(|x| x.id == message.id) {
+                stored.push_back(message);
+            }
+        }
+        stored.make_contiguous().sort_by_key(|x| x.id);
+        if config::get().context_eviction != EvictionStrategy::Age {
+            let excess = stored.len().saturating_sub(self.context_size);
+            stored.drain(..excess);
+        }
+        debug!("backfilled {channel_id}, which went from {before} to {} messages", stored.len());
+        self.evict_expired();
+    }
+
+    /// Clears the channels whose `/schedule-clear` interval has passed.
+    /// Intervals restart with the database, since clearing then is redundant.
+    fn clear_scheduled(&mut self) {
+        let now = Instant::now();
+        for (channel_id, interval_secs) in &self.state.clear_schedules {
+            let last_cleared = self.last_cleared.entry(*channel_id).or_insert(now);
+            if now.duration_since(*last_cleared) >= Duration::from_secs(*interval_secs) {
+                debug!("clearing the stored messages of {channel_id} on schedule");
+                self.messages.remove(channel_id);
+                *last_cleared = now;
+            }
+        }
+    }
+
+    /// The newest `n_latest` messages stored for `channel_id`, oldest first.
+    /// Empty, rather than an error, when nothing is stored for the channel yet.
+    fn validate(&mut self, channel_id: u64, ids: &[u64]) {
+        if let Some(messages) = self.messages.get_mut(&channel_id) {
+            messages.iter_mut()
+                .filter(|x| ids.contains(&x.id))
+                .for_each(|x| x.status = ValidationStatus::Validated);
+        }
+    }
+
+    fn latest(&self, channel_id: u64, n_latest: u8) -> Vec<PartialMessage> {
+        self.messages.get(&channel_id).map(|messages| {
+            let start = messages.len().saturating_sub(n_latest as usize);
+            messages.iter().skip(start).cloned().collect()
+        }).unwrap_or_default()
+    }
+
+    /// Drops messages older than `CONTEXT_TTL_SECS` under age-based eviction.
+    fn evict_expired(&mut self) {
+        let config = config::get();
+        let Some(ttl) = config.context_ttl_secs.filter(|_| config.context_eviction != EvictionStrategy::Count) else {
+            return;
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        self.messages.retain(|_, messages| {
+            messages.retain(|x| now - x.timestamp <= ttl);
+            !messages.is_empty()
+        });
+    }
+
+    fn save(&self) {
+        self.state.save(&config::get().state_path);
+    }
+}
+
+/// A recent message kept for spam-wave detection.
+struct Duplicate {
+    message: PartialMessage,
+    /// Already returned as part of a wave.
+    reported: bool
 }
 
+/// Roughly how much memory a stored message takes, counting its strings but not allocator overhead.
+fn estimated_size(message: &PartialMessage) -> usize {
+    std::mem::size_of::<PartialMessage>()
+        + message.content.len()
+        + message.previous_content.as_ref().map_or(0, String::len)
+        + message.reply_to.as_ref().map_or(0, |x| x.content.len())
+        + message.stickers.iter().chain(&message.notes).chain(&message.forwarded).chain(&message.components).map(String::len).sum::<usize>()
+        + message.poll.as_ref().map_or(0, |x| x.question.len() + x.answers.iter().map(String::len).sum::<usize>())
+}
+
+pub struct DatabaseStats {
+    pub context_size: usize,
+    pub channels: Vec<ChannelStats>
+}
+
+/// What `/dbstats` reports about one channel's stored messages.
+pub struct ChannelStats {
+    pub channel_id: u64,
+    pub messages: usize,
+    /// Unix timestamps of the oldest and newest stored message.
+    pub oldest: i64,
+    pub newest: i64,
+    pub bytes: usize
+}
+
+#[derive(Clone)]
 pub enum DatabaseMessage {
-    GetLatest(u8),
+    GetLatest { channel_id: u64, n_latest: u8 },
+    GetMessage { channel_id: u64, id: u64 },
+    /// Replies with the edited message, or `None` when it isn't stored or its content is unchanged.
+    EditMessage { channel_id: u64, id: u64, content: String },
+    /// Replies with the channel's stored messages in `status`, oldest first.
+    GetByStatus { channel_id: u64, status: ValidationStatus },
+    /// Stores the message as context only; it is not tracked for `DUPLICATE_AUTHORS`.
     InsertMessage(PartialMessage),
-    ValidateEntries(u64)
+    /// Adds messages fetched from Discord's history to the channel's context.
+    Backfill { channel_id: u64, messages: Vec<PartialMessage> },
+    /// Stores the message, then replies with `Inserted` for its channel, in a single round trip.
+    InsertAndGetLatest { message: PartialMessage, n_latest: u8 },
+    /// Marks the messages with `ids` as validated, once a verdict covered them.
+    ValidateEntries { channel_id: u64, ids: Vec<u64> },
+    /// Clears the channel every `interval_secs`, or stops doing so when `None`.
+    SetClearSchedule { channel_id: u64, interval_secs: Option<u64> },
+    /// Replies with all of the user's warnings, including the new one.
+    AddWarning { guild_id: u64, user_id: u64, warning: Warning },
+    GetWarnings { guild_id: u64, user_id: u64 },
+    ClearWarnings { guild_id: u64, user_id: u64 },
+    GetGuildSettings(u64),
+    /// Replies with the current context size and a summary of every channel that has stored messages.
+    Stats,
+    /// Replies with the newest message timestamp seen in every channel since the database started.
+    Activity,
+    /// Changes the per-channel capacity, dropping the oldest messages of channels that are over it.
+    SetContextSize(usize),
+    SetGuildEnabled { guild_id: u64, enabled: bool },
+    /// Falls back to `LOG_CHANNEL_ID` when `None`.
+    SetLogChannel { guild_id: u64, channel_id: Option<u64> },
+    /// Replies with every channel's profile name.
+    GetChannelProfiles,
+    /// Removes the channel's profile when `None`.
+    SetChannelProfile { channel_id: u64, profile: Option<String> },
+    /// Replies with every snapshot saved with `/save-profile`.
+    GetPromptSnapshots,
+    /// Replaces any snapshot already saved under `name`.
+    SavePromptSnapshot { name: String, snapshot: PromptSnapshot },
+    /// Saves the persistent state, replies with `Drained` and stops the thread.
+    /// Messages sent before it are still handled first.
+    Shutdown
+}
+
+impl DatabaseMessage {
+    /// What the message would have done, without any message content, for the dead-letter log.
+    pub fn summary(&self) -> String {
+        match self {
+            Self::GetLatest { channel_id, n_latest } => format!("get the latest {n_latest} messages in {channel_id}"),
+            Self::GetMessage { channel_id, id } => format!("get message {id} in {channel_id}"),
+            Self::EditMessage { channel_id, id, .. } => format!("edit message {id} in {channel_id}"),
+            Self::GetByStatus { channel_id, status } => format!("get {status:?} messages in {channel_id}"),
+            Self::InsertMessage(message) | Self::InsertAndGetLatest { message, .. } => {
+                format!("store message {} by {} in {}", message.id, message.author_id, message.channel_id)
+            },
+            Self::Backfill { channel_id, messages } => format!("backfill {} messages in {channel_id}", messages.len()),
+            Self::ValidateEntries { channel_id, ids } => format!("mark {} messages in {channel_id} as validated", ids.len()),
+            Self::SetClearSchedule { channel_id, interval_secs } => format!("set the clear schedule of {channel_id} to {interval_secs:?} seconds"),
+            Self::AddWarning { guild_id, user_id, warning } => format!("warn {user_id} in {guild_id} for message {}", warning.message_id),
+            Self::GetWarnings { guild_id, user_id } => format!("get the warnings of {user_id} in {guild_id}"),
+            Self::ClearWarnings { guild_id, user_id } => format!("clear the warnings of {user_id} in {guild_id}"),
+            Self::GetGuildSettings(guild_id) => format!("get the settings of {guild_id}"),
+            Self::Stats => "get stats".into(),
+            Self::Activity => "get channel activity".into(),
+            Self::SetContextSize(context_size) => format!("set the context size to {context_size}"),
+            Self::SetGuildEnabled { guild_id, enabled } => format!("set moderation in {guild_id} to {enabled}"),
+            Self::SetLogChannel { guild_id, channel_id } => format!("set the log channel of {guild_id} to {channel_id:?}"),
+            Self::GetChannelProfiles => "get channel profiles".into(),
+            Self::SetChannelProfile { channel_id, profile } => format!("set the profile of {channel_id} to {profile:?}"),
+            Self::GetPromptSnapshots => "get prompt snapshots".into(),
+            Self::SavePromptSnapshot { name, .. } => format!("save the prompt snapshot {name}"),
+            Self::Shutdown => "shut down".into()
+        }
+    }
+}
+
+/// Replies sent back for the query variants of [`DatabaseMessage`].
+pub enum DatabaseResponse {
+    Messages(Vec<PartialMessage>),
+    /// The channel's latest messages like `GetLatest`, and the spam wave the new message
+    /// completes or joins, if any.
+    Inserted { latest: Vec<PartialMessage>, wave: Vec<PartialMessage> },
+    Message(Option<Box<PartialMessage>>),
+    Warnings(Vec<Warning>),
+    GuildSettings(GuildSettings),
+    Stats(DatabaseStats),
+    /// Channel ids with the Unix timestamp of their newest message, in no particular order.
+    Activity(Vec<(u64, i64)>),
+    ChannelProfiles(HashMap<u64, String>),
+    PromptSnapshots(HashMap<String, PromptSnapshot>),
+    /// Every channel's stored messages, handed over by a database that shut down.
+    Drained(HashMap<u64, VecDeque<PartialMessage>>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database(messages: HashMap<u64, VecDeque<PartialMessage>>) -> Database {
+        let (sender, _) = unbounded();
+        let (_, receiver) = unbounded();
+        Database {
+            messages,
+            state: PersistentState::default(),
+            context_size: 10,
+            last_seen: HashMap::new(),
+            last_cleared: HashMap::new(),
+            duplicates: HashMap::new(),
+            sender,
+            receiver
+        }
+    }
+
+    #[test]
+    fn latest_of_an_unknown_channel_is_empty() {
+        let stored = VecDeque::from([PartialMessage::new(1, 10, 100, "hi".into(), ValidationStatus::Validated, 0)]);
+        let database = database(HashMap::from([(10, stored)]));
+        assert!(database.latest(20, 5).is_empty());
+        assert_eq!(database.latest(10, 5).len(), 1);
+    }
+
+    #[test]
+    fn validate_marks_only_the_judged_messages() {
+        let stored = (1..=3).map(|id| PartialMessage::new(id, 10, 100, "hi".into(), ValidationStatus::NotValidated, id as i64)).collect();
+        let mut database = database(HashMap::from([(10, stored)]));
+        database.validate(10, &[1, 2]);
+        let statuses = database.latest(10, 5).into_iter().map(|x| x.status).collect::<Vec<ValidationStatus>>();
+        assert_eq!(statuses, [ValidationStatus::Validated, ValidationStatus::Validated, ValidationStatus::NotValidated]);
+    }
 }
@@ -0,0 +1,90 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use crate::config::{self, Sampling};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Warning {
+    pub message_id: u64,
+    pub reason: String,
+    pub timestamp: i64
+}
+
+/// Settings a guild's admins control for their own server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GuildSettings {
+    /// Whether messages in the guild are moderated at all, toggled with `/toggle`.
+    pub enabled: bool,
+    /// Set with `/setlogchannel`, overriding `LOG_CHANNEL_ID`.
+    #[serde(default)]
+    pub log_channel_id: Option<u64>
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            enabled: config::get().guilds_enabled_by_default,
+            log_channel_id: None
+        }
+    }
+}
+
+/// The prompt, model and sampling parameters in use when `/save-profile` took it,
+/// switched back to with `/load-profile`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PromptSnapshot {
+    pub system_prompt: String,
+    pub model: String,
+    pub sampling: Sampling,
+    pub saved_at: i64
+}
+
+/// The part of the database that survives restarts, saved to `STATE_PATH` after every change.
+#[derive(Default, Deserialize, Serialize)]
+pub struct PersistentState {
+    /// Warnings per guild, then per user.
+    #[serde(default)]
+    pub warnings: HashMap<u64, HashMap<u64, Vec<Warning>>>,
+    /// Only guilds whose settings were changed have an entry.
+    #[serde(default)]
+    pub guilds: HashMap<u64, GuildSettings>,
+    /// Channels whose stored messages are cleared periodically, with the interval in seconds.
+    #[serde(default)]
+    pub clear_schedules: HashMap<u64, u64>,
+    /// Channels set to a `PROFILES` entry with `/profile`, by name.
+    #[serde(default)]
+    pub channel_profiles: HashMap<u64, String>,
+    /// Saved with `/save-profile`, by name.
+    #[serde(default)]
+    pub prompt_snapshots: HashMap<String, PromptSnapshot>,
+}
+
+impl PersistentState {
+    /// Loads the state at `path`, starting empty if there is none yet.
+    /// An unreadable file is moved aside rather than overwritten by the next save.
+    pub fn load(path: &str) -> Self {
+        let Ok(contents) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match serde_json::from_str(&contents) {
+            Ok(state) => state,
+            Err(why) => {
+                let backup = format!("{path}.corrupt");
+                error!("could not parse {path}, moving it to {backup}: {why}");
+                let _ = fs::rename(path, backup);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        // Write then rename so a crash mid-write never leaves a truncated file behind.
+        let temporary = Path::new(path).with_extension("tmp");
+        let result = fs::write(&temporary, serde_json::to_vec(self).unwrap())
+            .and_then(|_| fs::rename(&temporary, path));
+        if let Err(why) = result {
+            error!("failed to save state to {path}: {why}");
+        }
+    }
+}
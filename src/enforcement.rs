@@ -0,0 +1,266 @@
+use std::{future::Future, time::Duration};
+
+use serenity::all::{ChannelId, Colour, Context, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMember, GuildId, HttpError, MessageId, ModelError, ReactionType, Timestamp, UserId};
+use tracing::{debug, error, info, warn};
+use serde_json::json;
+use crate::{config::{self, EnforcementMode}, truncate, PartialMessage, Severity};
+
+/// How many times a rate-limited enforcement call is retried before giving up.
+const MAX_RETRIES: u32 = 3;
+
+enum Failure {
+    MissingPermissions,
+    NotFound,
+    RateLimited,
+    Other
+}
+
+impl From<&serenity::Error> for Failure {
+    fn from(why: &serenity::Error) -> Self {
+        match why {
+            serenity::Error::Model(ModelError::InvalidPermissions { .. }) => Self::MissingPermissions,
+            serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => match response.status_code.as_u16() {
+                403 => Self::MissingPermissions,
+                404 => Self::NotFound,
+                429 => Self::RateLimited,
+                _ => Self::Other
+            },
+            _ => Self::Other
+        }
+    }
+}
+
+/// Runs one enforcement call, logging failures instead of propagating them.
+/// Missing permissions and already-deleted targets are skipped, rate limits are
+/// retried with backoff. Returns `None` if the call did not succeed.
+async fn attempt<T, F, Fut>(action: &str, mut call: F) -> Option<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = serenity::Result<T>>
+{
+    let mut retries = 0;
+    loop {
+        let why = match call().await {
+            Ok(value) => return Some(value),
+            Err(why) => why
+        };
+        match Failure::from(&why) {
+            Failure::MissingPermissions => warn!("missing permissions to {action}, skipping: {why}"),
+            Failure::NotFound => debug!("could not {action}, it is already gone: {why}"),
+            Failure::RateLimited if retries < MAX_RETRIES => {
+                retries += 1;
+                let delay = Duration::from_secs(1 << retries);
+                warn!("rate limited trying to {action}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                continue;
+            },
+            Failure::RateLimited | Failure::Other => error!("failed to {action}: {why}")
+        }
+        return None;
+    }
+}
+
+/// Carries out the `SEVERITY_ACTIONS` tier or `ENFORCEMENT_MODE` for `severity` against
+/// a flagged message, returning what was done for the mod log.
+pub async fn enforce(ctx: &Context, message: &PartialMessage, reason: &str, severity: Option<Severity>) -> String {
+    info!("flagged message {} from {}: {reason}", message.id, message.author_id);
+    let (mode, timeout) = config::get().enforcement_for(severity);
+    let action = enforce_mode(ctx, message, reason, severity, mode).await;
+    let Some(guild_id) = message.guild_id.filter(|_| timeout) else {
+        return action.into();
+    };
+    match time_out(ctx, guild_id, message.author_id, reason).await {
+        true => format!("{action}, author timed out"),
+        false => format!("{action}, timeout failed")
+    }
+}
+
+async fn enforce_mode(ctx: &Context, message: &PartialMessage, reason: &str, severity: Option<Severity>, mode: EnforcementMode) -> &'static str {
+    match mode {
+        EnforcementMode::Log => "None, logged only",
+        EnforcementMode::Delete => match delete(ctx, message, reason).await {
+            true => "Deleted",
+            false => "Deletion failed"
+        },
+        // Keep the original if it could not be preserved elsewhere.
+        EnforcementMode::Quarantine => match quarantine(ctx, message, reason, severity).await {
+            true if delete(ctx, message, reason).await => "Quarantined and deleted",
+            true => "Quarantined, deletion failed",
+            false => "Quarantine failed, message kept"
+        }
+    }
+}
+
+/// Escalates against a user who now has `warnings` warnings: every flag is
+/// DMed to them, then `WARN_TIMEOUT_AT` and `WARN_KICK_AT` time them out or kick them.
+/// Nothing happens for a `severity` that is only logged, as [`enforce`] decides.
+pub async fn escalate(ctx: &Context, guild_id: u64, user_id: u64, reason: &str, severity: Option<Severity>, warnings: usize) {
+    let config = config::get();
+    if config.enforcement_for(severity).0 == EnforcementMode::Log {
+        info!("user {user_id} now has {warnings} warnings");
+        return;
+    }
+    let guild = GuildId::new(guild_id);
+    let user = UserId::new(user_id);
+    let dm = CreateMessage::new().content(format!("You received a warning ({warnings} so far): {}", shown_reason(reason)));
+    attempt(&format!("warn user {user_id}"), || user.direct_message(&ctx.http, dm.clone())).await;
+    if config.warn_kick_at.is_some_and(|x| warnings >= x) {
+        attempt(&format!("kick user {user_id}"), || guild.kick_with_reason(&ctx.http, user, reason)).await;
+    }else if config.warn_timeout_at.is_some_and(|x| warnings >= x) {
+        time_out(ctx, guild_id, user_id, reason).await;
+    }
+}
+
+/// Times `user_id` out for `TIMEOUT_SECS`. Returns whether they were timed out.
+async fn time_out(ctx: &Context, guild_id: u64, user_id: u64, reason: &str) -> bool {
+    let guild = GuildId::new(guild_id);
+    let user = UserId::new(user_id);
+    let until = Timestamp::from_unix_timestamp(Timestamp::now().unix_timestamp() + config::get().timeout_secs).unwrap();
+    let timeout = EditMember::new().disable_communication_until_datetime(until).audit_log_reason(reason);
+    attempt(&format!("time out user {user_id}"), || guild.edit_member(&ctx.http, user, timeout.clone())).await
+        .is_some()
+}
+
+/// Clears `user_id`'s nickname in `guild_id`, which needs the Manage Nicknames permission.
+/// Returns whether it was reset; usernames can't be changed by the bot, and names flagged
+/// with a `severity` that is only logged are left alone.
+pub async fn reset_nickname(ctx: &Context, guild_id: u64, user_id: u64, reason: &str, severity: Option<Severity>) -> bool {
+    if config::get().enforcement_for(severity).0 == EnforcementMode::Log {
+        return false;
+    }
+    let guild = GuildId::new(guild_id);
+    let user = UserId::new(user_id);
+    let reset = EditMember::new().nickname("").audit_log_reason(reason);
+    attempt(&format!("reset the nickname of user {user_id}"), || guild.edit_member(&ctx.http, user, reset.clone())).await
+        .is_some()
+}
+
+/// Deletes `message`, giving `reason` in the audit log. Returns whether it was deleted.
+pub async fn delete(ctx: &Context, message: &PartialMessage, reason: &str) -> bool {
+    let channel_id = ChannelId::new(message.channel_id);
+    let message_id = MessageId::new(message.id);
+    attempt(&format!("delete message {message_id}"), || ctx.http.delete_message(channel_id, message_id, Some(reason))).await
+        .is_some()
+}
+
+/// Re-posts the flagged message to `QUARANTINE_CHANNEL_ID` so mods can review it after deletion.
+/// Returns whether the copy was posted.
+async fn quarantine(ctx: &Context, message: &PartialMessage, reason: &str, severity: Option<Severity>) -> bool {
+    let Some(quarantine_channel) = config::get().quarantine_channel_id else {
+        error!("ENFORCEMENT_MODE is quarantine but QUARANTINE_CHANNEL_ID is not set");
+        return false;
+    };
+    let channel_id = ChannelId::new(message.channel_id);
+    // The stored copy may be truncated, so prefer the original while it still exists.
+    let content = match channel_id.message(&ctx.http, MessageId::new(message.id)).await {
+        Ok(original) => original.content,
+        Err(_) => message.content.clone()
+    };
+    let embed = flag_embed(message, reason, severity, None).description(content);
+    let quarantined = CreateMessage::new().embed(embed);
+    let quarantine_channel = ChannelId::new(quarantine_channel);
+    attempt(&format!("quarantine message {}", message.id), || quarantine_channel.send_message(&ctx.http, quarantined.clone())).await
+        .is_some()
+}
+
+/// The embed describing a flag, shared by the quarantine and mod-log posts.
+/// Colored by severity so serious flags stand out in a busy channel.
+pub fn flag_embed(message: &PartialMessage, reason: &str, severity: Option<Severity>, confidence: Option<f32>) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .color(severity_color(severity))
+        .field("Author", format!("<@{}>", message.author_id), true)
+        .field("Channel", format!("<#{}>", message.channel_id), true)
+        .field("Severity", severity.map_or("Unrated".into(), |x| format!("{x:?}")), true);
+    if let Some(confidence) = confidence {
+        embed = embed.field("Confidence", format!("{:.0}%", confidence * 100.0), true);
+    }
+    embed
+        .field("Reason", shown_reason(reason), false)
+        .field("Message", format!("[Jump to message]({})", message_link(message)), false)
+        .footer(CreateEmbedFooter::new(format!("message {}", message.id)))
+}
+
+/// The embed describing a flagged member name, for the mod log.
+pub fn name_embed(user_id: u64, names: &str, reason: &str, severity: Option<Severity>, action: &str) -> CreateEmbed {
+    CreateEmbed::new()
+        .title("Name flagged")
+        .color(severity_color(severity))
+        .field("Member", format!("<@{user_id}>"), true)
+        .field("Severity", severity.map_or("Unrated".into(), |x| format!("{x:?}")), true)
+        .field("Action taken", action, true)
+        .field("Names", names, false)
+        .field("Reason", shown_reason(reason), false)
+}
+
+fn severity_color(severity: Option<Severity>) -> Colour {
+    match severity {
+        Some(Severity::High) => Colour::RED,
+        Some(Severity::Medium) => Colour::ORANGE,
+        Some(Severity::Low) => Colour::GOLD,
+        None => Colour::LIGHT_GREY
+    }
+}
+
+/// `reason` as users and mods see it, cut to `MAX_REASON_LEN`.
+pub fn shown_reason(reason: &str) -> String {
+    match config::get().max_reason_len {
+        Some(max_len) => truncate(reason, max_len),
+        None => reason.into()
+    }
+}
+
+/// A link that opens `message` in the Discord client. Threads are channels of their own,
+/// so a thread message's link opens the thread rather than its parent.
+pub fn message_link(message: &PartialMessage) -> String {
+    let guild = message.guild_id.map_or("@me".into(), |x| x.to_string());
+    format!("https://discord.com/channels/{guild}/{}/{}", message.channel_id, message.id)
+}
+
+/// Reports the flag to `FLAG_WEBHOOK_URL` in the background, so a slow or failing
+/// webhook never holds up enforcement.
+pub fn post_webhook(client: &reqwest::Client, message: &PartialMessage, reason: &str, severity: Option<Severity>, confidence: Option<f32>) {
+    let Some(url) = config::get().flag_webhook_url.as_ref() else {
+        return;
+    };
+    let request = client.post(url).json(&json!({
+        "author_id": message.author_id.to_string(),
+        "channel_id": message.channel_id.to_string(),
+        "guild_id": message.guild_id.map(|x| x.to_string()),
+        "message_id": message.id.to_string(),
+        "reason": reason,
+        "severity": severity,
+        "confidence": confidence,
+        "link": message_link(message)
+    }));
+    let message_id = message.id;
+    tokio::spawn(async move {
+        match request.send().await.and_then(|x| x.error_for_status()) {
+            Ok(_) => debug!("posted flag for message {message_id} to the webhook"),
+            Err(why) => warn!("failed to post flag for message {message_id} to the webhook: {why}")
+        }
+    });
+}
+
+/// Reacts to `message` with `emoji` when `REACTION_FEEDBACK` is on.
+pub async fn react(ctx: &Context, message: &PartialMessage, emoji: char) {
+    if !config::get().reaction_feedback {
+        return;
+    }
+    let channel_id = ChannelId::new(message.channel_id);
+    let message_id = MessageId::new(message.id);
+    let reaction = ReactionType::Unicode(emoji.into());
+    // A missing Add Reactions permission is logged by `attempt` and otherwise ignored.
+    attempt(&format!("react to message {message_id}"), || ctx.http.create_reaction(channel_id, message_id, &reaction)).await;
+}
+
+/// Posts `embed` to `log_channel`, resolved from the guild's settings and `LOG_CHANNEL_ID`.
+/// Without one the flag is only in the bot's own log.
+pub async fn notify_mods(ctx: &Context, log_channel: Option<u64>, embed: CreateEmbed) {
+    let Some(log_channel) = log_channel else {
+        debug!("no log channel to notify mods in");
+        return;
+    };
+    let log_channel = ChannelId::new(log_channel);
+    let notification = CreateMessage::new().embed(embed);
+    attempt("notify mods", || log_channel.send_message(&ctx.http, notification.clone())).await;
+}
@@ -0,0 +1,183 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde::Deserialize;
+use serenity::all::{Attachment, Message};
+use tracing::warn;
+use whatlang::Lang;
+use crate::config::{self, Prefilter};
+
+/// Normalized characters a message needs before copies of it count towards `DUPLICATE_AUTHORS`.
+const MIN_DUPLICATE_LEN: usize = 20;
+
+/// What the `NSFW_FILTER_URL` endpoint answers for an image.
+#[derive(Deserialize)]
+struct Classification {
+    /// Likelihood that the image is NSFW, from 0 to 1.
+    score: f32
+}
+
+/// The detected language of `content` when it is outside `ALLOWED_LANGUAGES`.
+/// Short or ambiguous text is never reported since detection is unreliable on it.
+pub fn disallowed_language(content: &str) -> Option<Lang> {
+    let config = config::get();
+    if config.allowed_languages.is_empty() || content.chars().count() < config.language_min_length {
+        return None;
+    }
+    let info = whatlang::detect(content)?;
+    if !info.is_reliable() || config.allowed_languages.contains(&info.lang()) {
+        return None;
+    }
+    Some(info.lang())
+}
+
+/// The first attachment whose content type is in `BLOCKED_CONTENT_TYPES`.
+pub fn blocked_attachment(attachments: &[Attachment]) -> Option<&Attachment> {
+    let blocked = &config::get().blocked_content_types;
+    attachments.iter().find(|attachment| {
+        let Some(content_type) = &attachment.content_type else {
+            return false;
+        };
+        // Content types may carry parameters, such as `text/plain; charset=utf-8`.
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        blocked.iter().any(|x| match x.strip_suffix("/*") {
+            Some(kind) => essence.split('/').next() == Some(kind),
+            None => essence == *x
+        })
+    })
+}
+
+/// Why `content` should be flagged while the provider is unavailable, from `BLOCKED_WORDS`
+/// and link or mention counts past `DEGRADED_SPAM_THRESHOLD`. A crude stand-in for the model.
+pub fn degraded_reason(content: &str) -> Option<String> {
+    let config = config::get();
+    if let Some(word) = blocked_word(content) {
+        return Some(format!("Contains the blocked word {word:?}"));
+    }
+    let links = link_hosts(content).len();
+    if links >= config.degraded_spam_threshold {
+        return Some(format!("Contains {links} links"));
+    }
+    let mentions = content.matches("<@").count();
+    if mentions >= config.degraded_spam_threshold {
+        return Some(format!("Mentions {mentions} users and roles"));
+    }
+    None
+}
+
+/// The first of `BLOCKED_WORDS` that appears in `content` as a whole word.
+pub fn blocked_word(content: &str) -> Option<&'static str> {
+    let lowercase = content.to_lowercase();
+    let words = lowercase.split(|x: char| !x.is_alphanumeric()).collect::<Vec<&str>>();
+    config::get().blocked_words.iter().find(|x| words.contains(&x.as_str())).map(String::as_str)
+}
+
+/// A hash of `content` that ignores case, punctuation and spacing, so lightly varied
+/// copypasta still matches. Short messages get none, since many people say "hi".
+pub fn content_hash(content: &str) -> Option<u64> {
+    let normalized = content.to_lowercase()
+        .split(|x: char| !x.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if normalized.chars().count() < MIN_DUPLICATE_LEN {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    normalized.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// The first `PREFILTERS` entry matching `content`, with the text it matched.
+pub fn prefilter(content: &str) -> Option<(&'static Prefilter, String)> {
+    config::get().prefilters.iter()
+        .find_map(|x| x.pattern.find(content).map(|matched| (x, matched.as_str().to_string())))
+}
+
+/// How a message went past `MASS_MENTION_THRESHOLD`.
+pub enum MassMention {
+    /// Pinged, or tried to ping, @everyone or @here.
+    Everyone,
+    /// Mentioned this many users and roles.
+    Many(usize)
+}
+
+/// Checks `message` against `MASS_MENTION_THRESHOLD`. Attempts at @everyone and @here count
+/// even when the author lacks the permission and Discord did not deliver them.
+pub fn mass_mention(message: &Message) -> Option<MassMention> {
+    let threshold = config::get().mass_mention_threshold?;
+    if message.mention_everyone || message.content.contains("@everyone") || message.content.contains("@here") {
+        return Some(MassMention::Everyone);
+    }
+    let mentions = message.mentions.len() + message.mention_roles.len();
+    (mentions >= threshold).then_some(MassMention::Many(mentions))
+}
+
+/// Why a link in a message was singled out by `ALLOWED_DOMAINS`/`BLOCKED_DOMAINS`.
+pub enum LinkVerdict {
+    Blocked(String),
+    /// Not in `ALLOWED_DOMAINS`, only reported when it is set.
+    Unknown(String)
+}
+
+/// Checks every link in `content`, reporting a blocked domain over an unknown one.
+pub fn link_verdict(content: &str) -> Option<LinkVerdict> {
+    let config = config::get();
+    let hosts = link_hosts(content);
+    if let Some(host) = hosts.iter().find(|x| matches_domain(x, &config.blocked_domains)) {
+        return Some(LinkVerdict::Blocked(host.clone()));
+    }
+    if config.allowed_domains.is_empty() {
+        return None;
+    }
+    hosts.into_iter()
+        .find(|x| !matches_domain(x, &config.allowed_domains))
+        .map(LinkVerdict::Unknown)
+}
+
+fn link_hosts(content: &str) -> Vec<String> {
+    content.split_whitespace()
+        .filter_map(|word| {
+            let start = word.find("https://").or_else(|| word.find("http://"))?;
+            // Links are often wrapped in <> to suppress embeds, or end a sentence.
+            let link = word[start..].trim_end_matches(['>', ')', ']', '.', ',', '!', '?', '"', '\'']);
+            let url = reqwest::Url::parse(link).ok()?;
+            url.host_str().map(|x| x.to_ascii_lowercase())
+        })
+        .collect()
+}
+
+fn matches_domain(host: &str, domains: &[String]) -> bool {
+    domains.iter().any(|domain| host == domain || host.strip_suffix(domain.as_str()).is_some_and(|x| x.ends_with('.')))
+}
+
+/// The first image attachment that `NSFW_FILTER_URL` scores at or above `NSFW_THRESHOLD`,
+/// with its score. Attachments that are not images, or fail to download or classify, are skipped.
+pub async fn nsfw_attachment<'a>(client: &reqwest::Client, attachments: &'a [Attachment]) -> Option<(&'a Attachment, f32)> {
+    let config = config::get();
+    let url = config.nsfw_filter_url.as_ref()?;
+    for attachment in attachments {
+        let Some(content_type) = attachment.content_type.as_ref().filter(|x| x.starts_with("image/")) else {
+            continue;
+        };
+        match classify(client, url, &attachment.url, content_type).await {
+            Ok(score) if score >= config.nsfw_threshold => return Some((attachment, score)),
+            Ok(_) => {},
+            Err(why) => warn!("could not classify attachment {}: {why}", attachment.filename)
+        }
+    }
+    None
+}
+
+/// Downloads the image and POSTs it to the classifier as the raw request body.
+async fn classify(client: &reqwest::Client, classifier_url: &str, image_url: &str, content_type: &str) -> Result<f32, reqwest::Error> {
+    let image = client.get(image_url).send().await?.error_for_status()?.bytes().await?;
+    let classification = client.post(classifier_url)
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(image)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Classification>()
+        .await?;
+    Ok(classification.score)
+}
@@ -1,18 +1,250 @@
-use std::{cmp::Ordering, collections::VecDeque};
+use std::{cmp::Ordering, collections::{HashMap, VecDeque}, ops::Deref, str::FromStr, sync::{atomic::{self, AtomicBool}, Arc, Mutex, MutexGuard, RwLock}, time::{Duration, Instant}};
 
-use serenity::{all::{Context, EventHandler, GatewayIntents, Message}, async_trait};
+use serenity::{all::{ActionRow, ActionRowComponent, ButtonKind, ChannelId, Context, CreateEmbed, EventHandler, GatewayIntents, GuildMemberUpdateEvent, Interaction, LightMethod, Member, Message, MessageReferenceKind, MessageType, MessageUpdateEvent, Reaction, Ready, Request, Route, Timestamp, User}, async_trait};
+use tokio::sync::Semaphore;
 use serde::{Deserialize, Serialize};
-use database::definitions;
-use crossbeam::channel::{Sender, Receiver};
+use database::{connection::DatabaseConnection, definitions, state::{GuildSettings, PromptSnapshot, Warning}};
+use config::{BotMessages, ContextOrder, PrefilterAction, Profile, Sampling, SystemMessages, UncertainAction, UnknownDomainAction};
+use heuristics::{LinkVerdict, MassMention};
+use prompt::{Pseudonyms, Strictness};
+use provider::{ChatProvider, ChatResponse, Completion, ProviderError, Usage};
+use tracing::{debug, error, info, warn};
 
+pub mod commands;
+pub mod config;
 pub mod database;
+pub mod enforcement;
+pub mod heuristics;
+pub mod metrics;
+pub mod prompt;
+pub mod provider;
 
+/// How many stored messages are sent to the model as context by default.
+const PROMPT_CONTEXT: u8 = 20;
+/// How many parent-channel messages are added to a thread's context with `INCLUDE_THREAD_PARENT`.
+const THREAD_PARENT_CONTEXT: u8 = 5;
+/// How many provider and parse errors are kept for `/errors`.
+const RECENT_ERRORS: usize = 25;
+/// How many times a rate-limited provider request is retried before giving up.
+const MAX_PROVIDER_RETRIES: u32 = 3;
+/// The longest `Retry-After` honored; a provider asking for more is treated as failed.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// How many parse outcomes are kept for `/parse-stats`.
+const RECENT_PARSES: usize = 500;
+/// Characters of an unparseable response kept in its recorded error.
+const ERROR_RESPONSE_LEN: usize = 200;
+
+/// The model's verdict. A `reason` is what makes it a flag:
+/// - `user_id` and `reason`: act on that user's latest unreviewed message in the channel.
+/// - `reason` only: act on the message being evaluated.
+/// - `user_id` only: ambiguous, logged without action.
+/// - neither: the message is fine.
 #[derive(Debug, Deserialize, Serialize)]
 struct Validation {
-    user_id: Option<u64>,
-    reason: Option<String>
+    /// The aliases are names models tend to pick on their own.
+    #[serde(alias = "author_id")]
+    user_id: Option<Author>,
+    #[serde(alias = "explanation", alias = "violation")]
+    reason: Option<String>,
+    /// How sure the model is about the verdict, from 0 to 1.
+    #[serde(default)]
+    confidence: Option<f32>,
+    #[serde(default)]
+    severity: Option<Severity>
+}
+
+/// What the model concluded about a context, before anything is acted on.
+struct Verdict {
+    /// The message being evaluated.
+    newest: PartialMessage,
+    /// Ids of the messages from the newest one's channel that were in the prompt.
+    judged: Vec<u64>,
+    outcome: Outcome,
+    /// How long the provider took to answer.
+    latency: Duration,
+    usage: Option<Usage>
+}
+
+enum Outcome {
+    Failed(ProviderError),
+    /// No choice held a verdict that could be parsed.
+    Unparsed,
+    /// A verdict that cannot be acted on, with why.
+    Unusable(String),
+    Fine,
+    Flagged {
+        target: Box<PartialMessage>,
+        reason: String,
+        severity: Option<Severity>,
+        confidence: Option<f32>
+    }
+}
+
+/// Provider usage since the last `BUDGET_RESET_HOUR`, counted against the daily budgets.
+#[derive(Default)]
+struct DailyUsage {
+    /// Days since the epoch, shifted by `BUDGET_RESET_HOUR`.
+    day: i64,
+    requests: u64,
+    tokens: u64,
+    /// Whether mods were already told that today's budget is used up.
+    notified: bool
+}
+
+impl DailyUsage {
+    const DAY_SECS: i64 = 24 * 60 * 60;
+
+    fn today() -> i64 {
+        (Timestamp::now().unix_timestamp() - config::get().budget_reset_hour as i64 * 3600).div_euclid(Self::DAY_SECS)
+    }
+
+    /// Unix timestamp of the next reset.
+    fn resets_at(&self) -> i64 {
+        (self.day + 1) * Self::DAY_SECS + config::get().budget_reset_hour as i64 * 3600
+    }
+
+    /// Starts counting from zero once the reset hour has passed.
+    fn roll_over(&mut self) {
+        let today = Self::today();
+        if self.day != today {
+            if self.exhausted() {
+                info!("the daily AI budget reset, resuming moderation with the model");
+            }
+            *self = Self { day: today, ..Self::default() };
+        }
+    }
+
+    fn exhausted(&self) -> bool {
+        let config = config::get();
+        config.daily_request_budget.is_some_and(|x| self.requests >= x)
+            || config.daily_token_budget.is_some_and(|x| self.tokens >= x)
+    }
+}
+
+/// A flagged author as the model names them: by id, or by pseudonym under `ANONYMIZE_USERS`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Author {
+    Id(u64),
+    Pseudonym(String)
+}
+
+/// How serious a flagged violation is, ordered from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High
+}
+
+impl FromStr for Severity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            _ => Err(())
+        }
+    }
+}
+
+impl Validation {
+    /// Serialized into the system prompt, so the shape the model is asked for is
+    /// always the shape that gets parsed. Adding a field without updating this fails to compile.
+    fn example() -> Self {
+        Self {
+            user_id: Some(if config::get().anonymize_users {
+                Author::Pseudonym("User A".into())
+            }else{
+                Author::Id(123456789012345678)
+            }),
+            reason: Some("why the new message breaks the rules".into()),
+            confidence: Some(0.9),
+            severity: Some(Severity::Medium)
+        }
+    }
+
+    /// [`Validation::example`] as JSON, with fields renamed by `VALIDATION_FIELDS`.
+    fn example_json() -> String {
+        let mut example = serde_json::to_value(Self::example()).unwrap();
+        if let Some(object) = example.as_object_mut() {
+            for renamed in &config::get().validation_fields {
+                if let Some(value) = object.remove(&renamed.field) {
+                    object.insert(renamed.name.clone(), value);
+                }
+            }
+        }
+        example.to_string()
+    }
+
+    /// Parses a verdict, accepting the names `VALIDATION_FIELDS` asked the model for.
+    fn parse(content: &str) -> serde_json::Result<Self> {
+        let mut validation = serde_json::from_str::<serde_json::Value>(content)?;
+        if let Some(object) = validation.as_object_mut() {
+            for renamed in &config::get().validation_fields {
+                if let Some(value) = object.remove(&renamed.name) {
+                    object.insert(renamed.field.clone(), value);
+                }
+            }
+        }
+        serde_json::from_value(validation)
+    }
+
+    /// Picks one verdict out of several choices: the majority decision wins
+    /// and ties go to whatever the first choice decided.
+    fn majority(mut validations: Vec<Validation>) -> Option<Validation> {
+        let flagged = validations.iter().filter(|x| x.reason.is_some()).count();
+        let flag = match (flagged * 2).cmp(&validations.len()) {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => validations.first()?.reason.is_some()
+        };
+        let index = validations.iter().position(|x| x.reason.is_some() == flag)?;
+        Some(validations.swap_remove(index))
+    }
+
+    /// What this verdict means for the newest of `messages`: the message it flags, or why it can't
+    /// be acted on. A named author, resolved with `resolve_author`, is flagged for their latest
+    /// unreviewed message in the newest message's channel.
+    fn outcome(self, messages: &[PartialMessage], resolve_author: impl Fn(&Author) -> Option<u64>) -> Outcome {
+        let Some(newest) = messages.last() else {
+            return Outcome::Unusable("no message to judge".into());
+        };
+        let channel_id = newest.channel_id;
+        let user_id = match &self.user_id {
+            Some(author) => match resolve_author(author) {
+                Some(user_id) => Some(user_id),
+                None => return Outcome::Unusable(format!("model named {author:?} in {channel_id}, who is not a known author"))
+            },
+            None => None
+        };
+        let (target, reason) = match (user_id, self.reason) {
+            (Some(user_id), Some(reason)) => {
+                let target = messages.iter().rev().find(|x| {
+                    x.channel_id == channel_id && x.author_id == user_id && x.status == ValidationStatus::NotValidated
+                });
+                match target {
+                    Some(target) => (target, reason),
+                    None => return Outcome::Unusable(format!("model flagged {user_id}, who has no unreviewed message in {channel_id}: {reason}"))
+                }
+            },
+            (None, Some(reason)) => (newest, reason),
+            (Some(user_id), None) => return Outcome::Unusable(format!("model named {user_id} in {channel_id} without a reason, taking no action")),
+            (None, None) => return Outcome::Fine
+        };
+        Outcome::Flagged {
+            target: Box::new(target.clone()),
+            reason,
+            severity: self.severity,
+            confidence: self.confidence
+        }
+    }
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Serialize)]
 #[serde(untagged)]
 enum Role {
@@ -24,8 +256,8 @@ enum Role {
     Assistant
 }
 
-#[derive(Deserialize, Serialize)]
-struct AIMessage {
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AIMessage {
     content: Option<String>,
     role: String,
 }
@@ -39,96 +271,186 @@ impl AIMessage {
     }
 }
 
-#[derive(Deserialize, Serialize)]
-struct ResponseFormat {
-    r#type: String
+#[derive(Clone, Debug, Eq, Deserialize, Serialize)]
+pub struct PartialMessage {
+    id: u64,
+    /// The thread's own id for messages in threads, never its parent's, so links,
+    /// deletions and reactions all reach the message itself.
+    channel_id: u64,
+    #[serde(default)]
+    guild_id: Option<u64>,
+    author_id: u64,
+    content: String,
+    status: ValidationStatus,
+    timestamp: i64,
+    #[serde(default)]
+    stickers: Vec<String>,
+    /// The message this one replies to, which may have left the context window.
+    #[serde(default)]
+    reply_to: Option<RepliedMessage>,
+    /// Findings from deterministic checks that the model should weigh.
+    #[serde(default)]
+    notes: Vec<String>,
+    /// What the message said before its latest edit.
+    #[serde(default)]
+    previous_content: Option<String>,
+    /// The author joined the guild within `NEW_MEMBER_GRACE_SECS` of posting this.
+    #[serde(default)]
+    new_member: bool,
+    /// Polls carry their text outside of `content`.
+    #[serde(default)]
+    poll: Option<PollContent>,
+    /// Content of the messages this one forwards, which counts as the forwarding user's.
+    #[serde(default)]
+    forwarded: Vec<String>,
+    /// Labels of the message's buttons and select menus, where rule-breaking text can hide.
+    #[serde(default)]
+    components: Vec<String>
 }
 
-impl Default for ResponseFormat {
-    fn default() -> Self {
+/// Whether a stored message has already been through a moderation pass.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationStatus {
+    NotValidated,
+    Validated
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RepliedMessage {
+    id: u64,
+    author_id: u64,
+    content: String
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct PollContent {
+    question: String,
+    answers: Vec<String>
+}
+
+impl From<Message> for PartialMessage {
+    fn from(message: Message) -> Self {
         Self {
-            r#type: "json_object".into()
+            id: message.id.get(),
+            channel_id: message.channel_id.get(),
+            guild_id: message.guild_id.map(|x| x.get()),
+            author_id: message.author.id.get(),
+            content: stored_content(&message.content),
+            status: ValidationStatus::NotValidated,
+            timestamp: message.timestamp.unix_timestamp(),
+            stickers: message.sticker_items.iter().map(|x| x.name.clone()).collect(),
+            reply_to: message.referenced_message.as_ref().map(|x| RepliedMessage {
+                id: x.id.get(),
+                author_id: x.author.id.get(),
+                content: stored_content(&x.content)
+            }),
+            notes: Vec::new(),
+            previous_content: None,
+            new_member: false,
+            forwarded: Vec::new(),
+            poll: message.poll.as_ref().map(|x| PollContent {
+                question: stored_content(x.question.text.as_deref().unwrap_or_default()),
+                answers: x.answers.iter().filter_map(|x| x.poll_media.text.as_deref()).map(stored_content).collect()
+            }),
+            components: component_labels(&message.components)
         }
     }
 }
 
-#[derive(Deserialize, Serialize)]
-struct FireworksPayload {
-    model: String,
-    messages: Vec<AIMessage>,
-    response_format: Option<ResponseFormat>,
-    max_tokens: u64,
-    top_p: u8,
-    top_k: u8,
-    presence_penalty: u8,
-    frequency_penalty: u8,
-    temperature: f32,
-}
-
-impl Default for FireworksPayload {
-    fn default() -> Self {
-        let model = std::env::var("MODEL").unwrap();
-        Self {
-            model,
-            messages: Vec::new(),
-            response_format: Some(ResponseFormat::default()),
-            max_tokens: 4096,
-            top_p: 1,
-            top_k: 40,
-            presence_penalty: 0,
-            frequency_penalty: 0,
-            temperature: 0.6
+/// The visible text of every button, select menu and input in `rows`, with link buttons' targets.
+fn component_labels(rows: &[ActionRow]) -> Vec<String> {
+    let mut labels = Vec::new();
+    for component in rows.iter().flat_map(|x| &x.components) {
+        match component {
+            ActionRowComponent::Button(button) => {
+                let label = button.label.as_deref().unwrap_or_default();
+                match &button.data {
+                    ButtonKind::Link { url } => labels.push(format!("{label} ({url})")),
+                    _ if !label.is_empty() => labels.push(label.into()),
+                    _ => {}
+                }
+            },
+            ActionRowComponent::SelectMenu(menu) => {
+                labels.extend(menu.placeholder.clone());
+                for option in &menu.options {
+                    labels.push(option.label.clone());
+                    labels.extend(option.description.clone());
+                }
+            },
+            ActionRowComponent::InputText(input) => labels.extend(input.label.clone()),
+            _ => {}
         }
     }
+    labels.into_iter().map(|x| stored_content(&x)).collect()
 }
 
-#[derive(Deserialize, Serialize)]
-struct Choice {
-    index: usize,
-    message: AIMessage
+/// The part of a raw message that serenity does not deserialize yet.
+#[derive(Deserialize)]
+struct RawForward {
+    #[serde(default)]
+    message_snapshots: Vec<MessageSnapshot>
 }
 
-#[derive(Deserialize, Serialize)]
-struct AIResponse {
-    choices: Vec<Choice>,
-    created: i32,
-    id: String,
-    model: String,
-    object: String,
+#[derive(Deserialize)]
+struct MessageSnapshot {
+    message: SnapshotContent
 }
 
-#[derive(Clone, Debug, Eq, Deserialize, Serialize)]
-pub struct PartialMessage {
-    id: u64,
-    channel_id: u64,
-    author_id: u64,
-    content: String,
-    status: String,
-    timestamp: i64
+#[derive(Deserialize)]
+struct SnapshotContent {
+    #[serde(default)]
+    content: String
 }
 
-impl From<Message> for PartialMessage {
-    fn from(message: Message) -> Self {
-        Self {
-            id: message.id.get(),
-            channel_id: message.channel_id.get(),
-            author_id: message.author.id.get(),
-            content: message.content.clone(),
-            status: "not_validated".into(),
-            timestamp: message.timestamp.unix_timestamp()
-        }
+/// Sorts `messages` by `order`, except for the last one, the message being judged, which stays last.
+/// Context from a thread's parent can be newer than the thread message it is fetched for.
+fn order_context(mut messages: Vec<PartialMessage>, order: ContextOrder) -> Vec<PartialMessage> {
+    let Some(judged) = messages.pop() else {
+        return messages;
+    };
+    match order {
+        ContextOrder::Timestamp => messages.sort_by_key(|x| x.timestamp),
+        ContextOrder::Id => messages.sort_by_key(|x| x.id)
+    }
+    messages.push(judged);
+    messages
+}
+
+/// Cuts `content` down to `max_len` characters, marking that it was cut.
+fn truncate(content: &str, max_len: usize) -> String {
+    match content.char_indices().nth(max_len) {
+        Some((end, _)) => format!("{}…[truncated]", &content[..end]),
+        None => content.to_string()
+    }
+}
+
+/// Applies `MAX_STORED_CONTENT_LEN` to content about to be stored.
+fn stored_content(content: &str) -> String {
+    match config::get().max_stored_content_len {
+        Some(max_len) => truncate(content, max_len),
+        None => content.to_string()
     }
 }
 
 impl PartialMessage {
-    pub fn new(id: u64, channel_id: u64, author_id: u64, content: String, status: String, timestamp: i64) -> Self {
+    pub fn new(id: u64, channel_id: u64, author_id: u64, content: String, status: ValidationStatus, timestamp: i64) -> Self {
         Self {
             id,
             channel_id,
+            guild_id: None,
             author_id,
             content,
             status,
-            timestamp
+            timestamp,
+            stickers: Vec::new(),
+            reply_to: None,
+            notes: Vec::new(),
+            previous_content: None,
+            new_member: false,
+            poll: None,
+            forwarded: Vec::new(),
+            components: Vec::new()
         }
     }
 }
@@ -137,11 +459,9 @@ impl PartialEq for PartialMessage {
     fn eq(&self, other: &Self) -> bool {
         self.channel_id == other.channel_id && self.id == other.id
     }
-    fn ne(&self, other: &Self) -> bool {
-        self.channel_id != other.channel_id || self.id != other.id
-    }
 }
 
+#[allow(clippy::non_canonical_partial_ord_impl)]
 impl PartialOrd for PartialMessage {
     fn ge(&self, other: &Self) -> bool {
         self.timestamp >= other.timestamp
@@ -208,90 +528,1053 @@ impl Ord for PartialMessage {
     }
 }
 
-struct Handler {
+/// The event handler, a cheap handle to [`HandlerState`]. Clones share the same database
+/// thread, HTTP client and runtime state, so background tasks can hold their own.
+#[derive(Clone)]
+pub struct Handler(Arc<HandlerState>);
+
+impl Deref for Handler {
+    type Target = HandlerState;
+
+    fn deref(&self) -> &HandlerState {
+        &self.0
+    }
+}
+
+pub struct HandlerState {
     id: u64,
-    database_connection: (Sender<definitions::DatabaseMessage>, Receiver<Vec<PartialMessage>>),
-    web_client: reqwest::Client
+    database: DatabaseConnection,
+    web_client: reqwest::Client,
+    provider: Box<dyn ChatProvider>,
+    /// `FALLBACK_PROVIDER` and `FALLBACK_MODEL`, tried when the primary provider fails.
+    fallback: Option<(Box<dyn ChatProvider>, String)>,
+    /// Starts as `MODEL`, swapped at runtime with `/setmodel` and `/load-profile`.
+    model: RwLock<String>,
+    /// Replaces `SYSTEM_PROMPT` and the sampling parameters once loaded with `/load-profile`.
+    prompt_snapshot: RwLock<Option<PromptSnapshot>>,
+    /// Caps concurrent provider requests at `MAX_CONCURRENT_AI`.
+    ai_permits: Semaphore,
+    /// Set while the provider is failing and messages are only checked with heuristics.
+    degraded: AtomicBool,
+    /// Set with `/ai-pause`: messages are still stored and checked with heuristics, but not sent to the model.
+    ai_paused: AtomicBool,
+    /// Counted against `DAILY_REQUEST_BUDGET` and `DAILY_TOKEN_BUDGET`, shown by `/usage`.
+    usage: Mutex<DailyUsage>,
+    /// Recent reaction times per user, for reaction-spam detection.
+    reactions: Mutex<HashMap<u64, VecDeque<Instant>>>,
+    /// Each member's names as last checked under `MODERATE_NICKNAMES`, keyed by guild and user.
+    names: Mutex<HashMap<(u64, u64), String>>,
+    /// When each user was last acted on, for `FLAG_COOLDOWN_SECS`.
+    last_action: Mutex<HashMap<u64, Instant>>,
+    /// Per-channel strictness set with `/strictness`, and when it resets to normal.
+    strictness: RwLock<HashMap<u64, (Strictness, Option<Instant>)>>,
+    /// Per-channel profile names set with `/profile`, loaded from the database at startup.
+    profiles: RwLock<HashMap<u64, String>>,
+    /// Cached `SUMMARIZE_CONTEXT` summaries per channel, and when they were made.
+    summaries: Mutex<HashMap<u64, (String, Instant)>>,
+    /// The latest provider and parse errors with their unix timestamps, oldest first, for `/errors`.
+    errors: Mutex<VecDeque<(i64, String)>>,
+    /// Whether each of the latest choices parsed into a verdict, with why not, for `/parse-stats`.
+    parses: Mutex<VecDeque<Result<(), String>>>,
+    /// Pseudonyms handed out under `ANONYMIZE_USERS`.
+    pseudonyms: Mutex<Pseudonyms>
 }
 
 impl Handler {
-    async fn ai_request(&self, messages: Vec<PartialMessage>) {
-        let channel_id = messages[0].channel_id;
-        let api_key = std::env::var("FIREWORKS_API_KEY").unwrap();
-        let system_prompt = std::env::var("SYSTEM_PROMPT").unwrap();
-        let system_prompt = AIMessage::new(Some(system_prompt), "system".into());
-        let mut ai_messages = messages.iter().map(|x| {
-            let content = format!("AUTHOR: {}\nCONTENT: {}\nVALIDATION_STATUS: {}", x.author_id, x.content, x.status);
-            AIMessage::new(Some(content), "user".into())
-        }).collect::<VecDeque<AIMessage>>();
-        ai_messages.push_front(system_prompt);
-        let mut payload = FireworksPayload::default();
-        payload.messages = ai_messages.into();
-        let response = self.web_client.post("https://api.fireworks.ai/inference/v1/chat/completions")
-            .bearer_auth(api_key)
-            .json(&payload)
-            .send()
-            .await;
-        if let Ok(response) = response {
-            if response.status().as_u16() != 200 {
-                panic!("{:?}", response);
+    /// The database's reply, or `None` when it could not answer even after a restart.
+    fn query(&self, message: definitions::DatabaseMessage) -> Option<definitions::DatabaseResponse> {
+        self.database.query(message)
+    }
+
+    fn get_latest(&self, channel_id: u64, n_latest: u8) -> Vec<PartialMessage> {
+        match self.query(definitions::DatabaseMessage::GetLatest { channel_id, n_latest }) {
+            Some(definitions::DatabaseResponse::Messages(messages)) => messages,
+            None => Vec::new(),
+            Some(_) => unreachable!("GetLatest is answered with Messages")
+        }
+    }
+
+    fn get_message(&self, channel_id: u64, id: u64) -> Option<PartialMessage> {
+        match self.query(definitions::DatabaseMessage::GetMessage { channel_id, id }) {
+            Some(definitions::DatabaseResponse::Message(message)) => message.map(|x| *x),
+            None => None,
+            Some(_) => unreachable!("GetMessage is answered with Message")
+        }
+    }
+
+    /// Stores `message`, returning the latest messages in its channel and the spam wave it is part of.
+    fn insert_and_get_latest(&self, message: PartialMessage, n_latest: u8) -> (Vec<PartialMessage>, Vec<PartialMessage>) {
+        match self.query(definitions::DatabaseMessage::InsertAndGetLatest { message, n_latest }) {
+            Some(definitions::DatabaseResponse::Inserted { latest, wave }) => (latest, wave),
+            None => (Vec::new(), Vec::new()),
+            Some(_) => unreachable!("InsertAndGetLatest is answered with Inserted")
+        }
+    }
+
+    /// Replaces a stored message's content, returning it unless it isn't stored or didn't change.
+    fn edit_message(&self, channel_id: u64, id: u64, content: String) -> Option<PartialMessage> {
+        match self.query(definitions::DatabaseMessage::EditMessage { channel_id, id, content }) {
+            Some(definitions::DatabaseResponse::Message(message)) => message.map(|x| *x),
+            None => None,
+            Some(_) => unreachable!("EditMessage is answered with Message")
+        }
+    }
+
+    fn get_by_status(&self, channel_id: u64, status: ValidationStatus) -> Vec<PartialMessage> {
+        match self.query(definitions::DatabaseMessage::GetByStatus { channel_id, status }) {
+            Some(definitions::DatabaseResponse::Messages(messages)) => messages,
+            None => Vec::new(),
+            Some(_) => unreachable!("GetByStatus is answered with Messages")
+        }
+    }
+
+    fn add_warning(&self, guild_id: u64, user_id: u64, warning: Warning) -> Vec<Warning> {
+        match self.query(definitions::DatabaseMessage::AddWarning { guild_id, user_id, warning }) {
+            Some(definitions::DatabaseResponse::Warnings(warnings)) => warnings,
+            None => Vec::new(),
+            Some(_) => unreachable!("AddWarning is answered with Warnings")
+        }
+    }
+
+    fn get_warnings(&self, guild_id: u64, user_id: u64) -> Vec<Warning> {
+        match self.query(definitions::DatabaseMessage::GetWarnings { guild_id, user_id }) {
+            Some(definitions::DatabaseResponse::Warnings(warnings)) => warnings,
+            None => Vec::new(),
+            Some(_) => unreachable!("GetWarnings is answered with Warnings")
+        }
+    }
+
+    fn guild_settings(&self, guild_id: u64) -> GuildSettings {
+        match self.query(definitions::DatabaseMessage::GetGuildSettings(guild_id)) {
+            Some(definitions::DatabaseResponse::GuildSettings(settings)) => settings,
+            None => GuildSettings::default(),
+            Some(_) => unreachable!("GetGuildSettings is answered with GuildSettings")
+        }
+    }
+
+    fn stats(&self) -> definitions::DatabaseStats {
+        match self.query(definitions::DatabaseMessage::Stats) {
+            Some(definitions::DatabaseResponse::Stats(stats)) => stats,
+            None => definitions::DatabaseStats { context_size: self.database.context_size(), channels: Vec::new() },
+            Some(_) => unreachable!("Stats is answered with Stats")
+        }
+    }
+
+    /// Every channel with the timestamp of the newest message seen in it.
+    fn activity(&self) -> Vec<(u64, i64)> {
+        match self.query(definitions::DatabaseMessage::Activity) {
+            Some(definitions::DatabaseResponse::Activity(activity)) => activity,
+            None => Vec::new(),
+            Some(_) => unreachable!("Activity is answered with Activity")
+        }
+    }
+
+    /// Whether messages by `author` are left alone: the bot's own, and other bots' per `BOT_MESSAGES`.
+    fn ignores(&self, author: &User) -> bool {
+        let config = config::get();
+        author.id.get() == self.id
+            || author.bot && (config.bot_messages == BotMessages::Ignore || config.ignored_bot_ids.contains(&author.id.get()))
+    }
+
+    /// The content of `msg` past `BYPASS_PREFIX`, when its author has a `BYPASS_ROLE_IDS` role.
+    fn bypassed_content<'a>(&self, msg: &'a Message) -> Option<&'a str> {
+        let config = config::get();
+        let content = msg.content.strip_prefix(config.bypass_prefix.as_deref()?)?;
+        let roles = &msg.member.as_ref()?.roles;
+        roles.iter().any(|x| config.bypass_role_ids.contains(&x.get())).then(|| content.trim_start())
+    }
+
+    /// Where mods of `guild_id` are notified: the guild's own log channel, or `LOG_CHANNEL_ID`.
+    fn log_channel(&self, guild_id: Option<u64>) -> Option<u64> {
+        guild_id.and_then(|x| self.guild_settings(x).log_channel_id)
+            .or(config::get().log_channel_id)
+    }
+
+    /// Records a reaction by `user_id` and reports whether they have now added
+    /// `REACTION_SPAM_THRESHOLD` reactions within `REACTION_SPAM_WINDOW_SECS`.
+    fn is_reaction_spam(&self, user_id: u64, threshold: usize) -> bool {
+        let window = Duration::from_secs(config::get().reaction_spam_window_secs);
+        let now = Instant::now();
+        let mut reactions = self.reactions.lock().unwrap();
+        let recent = reactions.entry(user_id).or_default();
+        while recent.front().is_some_and(|x| now.duration_since(*x) > window) {
+            recent.pop_front();
+        }
+        recent.push_back(now);
+        if recent.len() >= threshold {
+            recent.clear();
+            return true;
+        }
+        false
+    }
+
+    /// Whether `user_id` was acted on within the cooldown. Otherwise starts a new cooldown for them.
+    fn in_flag_cooldown(&self, user_id: u64) -> bool {
+        let cooldown = Duration::from_secs(config::get().flag_cooldown_secs);
+        let now = Instant::now();
+        let mut last_action = self.last_action.lock().unwrap();
+        if last_action.get(&user_id).is_some_and(|x| now.duration_since(*x) < cooldown) {
+            return true;
+        }
+        last_action.insert(user_id, now);
+        false
+    }
+
+    /// Whether the author of `msg` joined its guild within `NEW_MEMBER_GRACE_SECS`.
+    /// Gateway messages carry the member, which is only fetched when it is missing.
+    async fn is_new_member(&self, ctx: &Context, msg: &Message) -> bool {
+        let (Some(grace), Some(guild_id)) = (config::get().new_member_grace_secs, msg.guild_id) else {
+            return false;
+        };
+        let joined_at = match msg.member.as_ref().and_then(|x| x.joined_at) {
+            Some(joined_at) => Some(joined_at),
+            None => guild_id.member(ctx, msg.author.id).await
+                .inspect_err(|why| warn!("failed to fetch member {} in {guild_id}: {why}", msg.author.id))
+                .ok()
+                .and_then(|x| x.joined_at)
+        };
+        joined_at.is_some_and(|x| Timestamp::now().unix_timestamp() - x.unix_timestamp() < grace)
+    }
+
+    /// Whether the author of `msg` may ping @everyone in its channel, which exempts them from mass-mention checks.
+    async fn can_mention_everyone(&self, ctx: &Context, msg: &Message) -> bool {
+        let (Some(guild_id), Some(member)) = (msg.guild_id, msg.member.as_deref()) else {
+            return false;
+        };
+        let guild = match guild_id.to_partial_guild(ctx).await {
+            Ok(guild) => guild,
+            Err(why) => {
+                warn!("failed to fetch guild {guild_id}: {why}");
+                return false;
             }
-            let body : AIResponse = response.json().await.unwrap();
-            let choice = &body.choices[0];
-            let content = choice.message.content.clone().unwrap();
-            let validation = serde_json::from_str::<Validation>(&content).unwrap();
-            if let Some(reason) = validation.reason {
-                let (sender, _) = &self.database_connection;
-                let _ = sender.send(definitions::DatabaseMessage::ValidateEntries(channel_id));
-                println!("{reason}");
-            }else{
-                println!("this message is fine");
+        };
+        let Some(channel) = msg.channel_id.to_channel(ctx).await.ok().and_then(|x| x.guild()) else {
+            return false;
+        };
+        guild.partial_member_permissions_in(&channel, msg.author.id, member).mention_everyone()
+    }
+
+    /// The content `msg` forwards, when it is a forward. Serenity drops `message_snapshots`,
+    /// so the raw message is fetched again and only that field is read.
+    async fn forwarded_content(&self, ctx: &Context, msg: &Message) -> Vec<String> {
+        if msg.message_reference.as_ref().is_none_or(|x| x.kind != MessageReferenceKind::Forward) {
+            return Vec::new();
+        }
+        let route = Route::ChannelMessage { channel_id: msg.channel_id, message_id: msg.id };
+        let raw = match ctx.http.request(Request::new(route, LightMethod::Get)).await {
+            Ok(response) => response.text().await.unwrap_or_default(),
+            Err(why) => {
+                warn!("failed to fetch forwarded message {} in {}: {why}", msg.id, msg.channel_id);
+                return Vec::new();
+            }
+        };
+        match serde_json::from_str::<RawForward>(&raw) {
+            Ok(forward) => forward.message_snapshots.into_iter().map(|x| stored_content(&x.message.content)).collect(),
+            Err(why) => {
+                warn!("could not parse forwarded message {}: {why}", msg.id);
+                Vec::new()
             }
         }
     }
+
+    /// Recent messages from the parent channel when `channel_id` is a thread.
+    async fn thread_parent_context(&self, ctx: &Context, channel_id: ChannelId) -> Vec<PartialMessage> {
+        let Ok(channel) = channel_id.to_channel(ctx).await else {
+            return Vec::new();
+        };
+        match channel.guild() {
+            Some(thread) if thread.thread_metadata.is_some() => thread.parent_id
+                .map(|parent_id| self.get_latest(parent_id.get(), THREAD_PARENT_CONTEXT))
+                .unwrap_or_default(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Acts on a flagged message unless its author is still in their flag cooldown,
+    /// then reports it to the mod log. `confidence` is only known for the model's flags.
+    async fn flag(&self, ctx: &Context, message: &PartialMessage, reason: &str, severity: Option<Severity>, confidence: Option<f32>) {
+        if self.in_flag_cooldown(message.author_id) {
+            info!("user {} was flagged again during their cooldown, not acting: {reason}", message.author_id);
+            return;
+        }
+        metrics::increment(&metrics::get().flags);
+        enforcement::react(ctx, message, '🚫').await;
+        enforcement::post_webhook(&self.web_client, message, reason, severity, confidence);
+        let action = enforcement::enforce(ctx, message, reason, severity).await;
+        let embed = enforcement::flag_embed(message, reason, severity, confidence)
+            .title("Message flagged")
+            .field("Action taken", action, true);
+        enforcement::notify_mods(ctx, self.log_channel(message.guild_id), embed).await;
+        self.warn_author(ctx, message, reason, severity).await;
+    }
+
+    /// Checks a member's names under `MODERATE_NICKNAMES`, against `BLOCKED_WORDS` first and
+    /// then the model. A flagged nickname is reset; usernames can only be reported to mods.
+    async fn check_names(&self, ctx: &Context, event: &GuildMemberUpdateEvent) {
+        let guild_id = event.guild_id.get();
+        let user_id = event.user.id.get();
+        let names = [("NICKNAME", event.nick.as_deref()), ("DISPLAY NAME", event.user.global_name.as_deref()), ("USERNAME", Some(event.user.name.as_str()))]
+            .into_iter()
+            .filter_map(|(label, name)| name.map(|x| format!("{label}: {x}")))
+            .collect::<Vec<String>>()
+            .join("\n");
+        // Role and avatar changes arrive as member updates too, so unchanged names are not checked again.
+        if self.names.lock().unwrap().insert((guild_id, user_id), names.clone()).is_some_and(|x| x == names) {
+            return;
+        }
+        let flagged = match heuristics::blocked_word(&names) {
+            Some(word) => Some((format!("Name contains the blocked word {word:?}"), Some(Severity::Medium))),
+            None => self.name_verdict(guild_id, user_id, &names).await
+        };
+        let Some((reason, severity)) = flagged else {
+            return;
+        };
+        info!("flagged the names of {user_id} in {guild_id}: {reason}");
+        metrics::increment(&metrics::get().flags);
+        let action = match event.nick {
+            Some(_) if enforcement::reset_nickname(ctx, guild_id, user_id, &reason, severity).await => "Nickname reset",
+            Some(_) => "None, nickname not reset",
+            None => "None, usernames can't be changed"
+        };
+        let embed = enforcement::name_embed(user_id, &names, &reason, severity, action);
+        enforcement::notify_mods(ctx, self.log_channel(Some(guild_id)), embed).await;
+    }
+
+    /// Asks the model whether `names` break the rules, presenting them as a message by the member.
+    async fn name_verdict(&self, guild_id: u64, user_id: u64, names: &str) -> Option<(String, Option<Severity>)> {
+        if self.ai_paused.load(atomic::Ordering::Relaxed) {
+            return None;
+        }
+        let mut message = PartialMessage::new(0, 0, user_id, names.into(), ValidationStatus::NotValidated, Timestamp::now().unix_timestamp());
+        message.guild_id = Some(guild_id);
+        message.notes.push("this is not a chat message but the author's names on this server; flag names that are offensive or break the rules".into());
+        match self.verdict(vec![message], None).await?.outcome {
+            Outcome::Flagged { reason, severity, .. } => Some((reason, severity)),
+            Outcome::Failed(why) => {
+                warn!("could not check the names of {user_id} in {guild_id}: {why}");
+                None
+            },
+            _ => None
+        }
+    }
+
+    /// Records a warning against the author of `message` and escalates against them as `severity` calls for.
+    async fn warn_author(&self, ctx: &Context, message: &PartialMessage, reason: &str, severity: Option<Severity>) {
+        let Some(guild_id) = message.guild_id else {
+            return;
+        };
+        let warning = Warning {
+            message_id: message.id,
+            reason: reason.into(),
+            timestamp: Timestamp::now().unix_timestamp()
+        };
+        let warnings = self.add_warning(guild_id, message.author_id, warning).len();
+        enforcement::escalate(ctx, guild_id, message.author_id, reason, severity, warnings).await;
+    }
+
+    /// The pseudonyms to name authors by in prompts, when `ANONYMIZE_USERS` is on.
+    fn pseudonyms(&self) -> Option<MutexGuard<'_, Pseudonyms>> {
+        config::get().anonymize_users.then(|| self.pseudonyms.lock().unwrap())
+    }
+
+    /// The user the model flagged, or `None` for a pseudonym that was never handed out.
+    fn resolve_author(&self, author: &Author) -> Option<u64> {
+        match author {
+            Author::Id(user_id) => Some(*user_id),
+            Author::Pseudonym(name) => self.pseudonyms.lock().unwrap().resolve(name)
+                .or_else(|| name.trim().parse().ok())
+        }
+    }
+
+    fn record_parse(&self, parse: Result<(), String>) {
+        let mut parses = self.parses.lock().unwrap();
+        if parses.len() >= RECENT_PARSES {
+            parses.pop_front();
+        }
+        parses.push_back(parse);
+    }
+
+    fn record_error(&self, error: String) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() >= RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back((Timestamp::now().unix_timestamp(), error));
+    }
+
+    /// Sends `messages` to the provider once a `MAX_CONCURRENT_AI` permit is free,
+    /// failing over to the fallback provider when the primary one errors.
+    async fn chat(&self, messages: Vec<AIMessage>, sampling: Sampling) -> Result<ChatResponse, ProviderError> {
+        self.check_budget()?;
+        let _permit = self.ai_permits.acquire().await.unwrap();
+        let model = self.model.read().unwrap().clone();
+        let Some((fallback, fallback_model)) = &self.fallback else {
+            return self.chat_with(self.provider.as_ref(), &model, messages, sampling).await;
+        };
+        match self.chat_with(self.provider.as_ref(), &model, messages.clone(), sampling).await {
+            Ok(response) => Ok(response),
+            Err(why) => {
+                warn!("{} failed, failing over to {} ({fallback_model}): {why}", self.provider.name(), fallback.name());
+                self.chat_with(fallback.as_ref(), fallback_model, messages, sampling).await
+            }
+        }
+    }
+
+    /// Sends `messages` to the primary provider with `model` in place of the configured one.
+    /// There is no failover, so the answer always comes from `model`.
+    async fn chat_as(&self, model: &str, messages: Vec<AIMessage>, sampling: Sampling) -> Result<ChatResponse, ProviderError> {
+        self.check_budget()?;
+        let _permit = self.ai_permits.acquire().await.unwrap();
+        self.chat_with(self.provider.as_ref(), model, messages, sampling).await
+    }
+
+    /// Refuses to send anything once a daily budget is used up.
+    fn check_budget(&self) -> Result<(), ProviderError> {
+        let mut usage = self.usage.lock().unwrap();
+        usage.roll_over();
+        if usage.exhausted() {
+            return Err(ProviderError::OverBudget);
+        }
+        Ok(())
+    }
+
+    /// Counts one provider request, and the tokens it used when the provider reports them.
+    fn record_usage(&self, tokens: Option<Usage>) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.roll_over();
+        let exhausted = usage.exhausted();
+        usage.requests += 1;
+        usage.tokens += tokens.map_or(0, |x| x.prompt_tokens + x.completion_tokens);
+        if usage.exhausted() && !exhausted {
+            warn!("the daily AI budget is used up after {} requests and {} tokens, moderating with heuristics only", usage.requests, usage.tokens);
+        }
+    }
+
+    /// Sends `messages` to `provider`, retrying a rate-limited request after the provider's
+    /// `Retry-After`, or with exponential backoff when it sends none.
+    async fn chat_with(&self, provider: &dyn ChatProvider, model: &str, messages: Vec<AIMessage>, sampling: Sampling) -> Result<ChatResponse, ProviderError> {
+        let metrics = metrics::get();
+        let mut retries = 0;
+        let response = loop {
+            metrics::increment(&metrics.ai_requests);
+            let started = Instant::now();
+            let response = provider.chat(&self.web_client, model, messages.clone(), sampling).await;
+            metrics.provider_latency.record(started.elapsed());
+            self.record_usage(response.as_ref().ok().and_then(|x| x.usage));
+            let Err(ProviderError::RateLimited(retry_after, _)) = &response else {
+                break response;
+            };
+            let delay = retry_after.unwrap_or(Duration::from_secs(1 << (retries + 1)));
+            // Waiting longer would hold an AI permit for too long, so fail over instead.
+            if retries >= MAX_PROVIDER_RETRIES || delay > MAX_RETRY_AFTER {
+                break response;
+            }
+            retries += 1;
+            warn!("{} ({model}) rate limited the request, retrying in {delay:?}", provider.name());
+            tokio::time::sleep(delay).await;
+        };
+        match &response {
+            Ok(_) => debug!("{} ({model}) served the request", provider.name()),
+            Err(why) => {
+                metrics::increment(&metrics.provider_errors);
+                self.record_error(format!("{} {why}", provider.name()));
+            }
+        }
+        response
+    }
+
+    fn channel_strictness(&self, channel_id: u64) -> Strictness {
+        let strictness = self.strictness.read().unwrap().get(&channel_id).copied();
+        match strictness {
+            Some((_, Some(expires))) if Instant::now() >= expires => {
+                self.strictness.write().unwrap().remove(&channel_id);
+                Strictness::Normal
+            },
+            Some((strictness, _)) => strictness,
+            None => Strictness::Normal
+        }
+    }
+
+    /// The system prompt rules and base sampling parameters: the loaded `/load-profile`
+    /// snapshot's, or `SYSTEM_PROMPT` and the configured ones.
+    fn prompt_and_sampling(&self) -> (String, Sampling) {
+        match &*self.prompt_snapshot.read().unwrap() {
+            Some(snapshot) => (snapshot.system_prompt.clone(), snapshot.sampling),
+            None => (config::get().system_prompt.clone(), config::get().sampling)
+        }
+    }
+
+    fn prompt_snapshots(&self) -> HashMap<String, PromptSnapshot> {
+        match self.query(definitions::DatabaseMessage::GetPromptSnapshots) {
+            Some(definitions::DatabaseResponse::PromptSnapshots(snapshots)) => snapshots,
+            None => HashMap::new(),
+            Some(_) => unreachable!("GetPromptSnapshots is answered with PromptSnapshots")
+        }
+    }
+
+    /// The channel's `/profile`, unless it names a profile no longer in `PROFILES`.
+    fn channel_profile(&self, channel_id: u64) -> Option<&'static Profile> {
+        let profiles = self.profiles.read().unwrap();
+        let name = profiles.get(&channel_id)?;
+        let profile = config::get().profile(name);
+        if profile.is_none() {
+            warn!("{channel_id} is set to the profile {name}, which is not in PROFILES");
+        }
+        profile
+    }
+
+    /// Sets the channel's profile, or removes it when `None`, and persists the choice.
+    fn set_channel_profile(&self, channel_id: u64, profile: Option<String>) {
+        match &profile {
+            Some(profile) => self.profiles.write().unwrap().insert(channel_id, profile.clone()),
+            None => self.profiles.write().unwrap().remove(&channel_id)
+        };
+        self.database.send(definitions::DatabaseMessage::SetChannelProfile { channel_id, profile });
+    }
+
+    fn set_channel_strictness(&self, channel_id: u64, strictness: Strictness, duration: Option<Duration>) {
+        let mut channels = self.strictness.write().unwrap();
+        if strictness == Strictness::Normal {
+            channels.remove(&channel_id);
+        }else{
+            channels.insert(channel_id, (strictness, duration.map(|x| Instant::now() + x)));
+        }
+    }
+
+    /// The cached summary of `channel_id`'s older context, regenerated from `older`
+    /// once it is past `SUMMARY_REFRESH_SECS`.
+    async fn context_summary(&self, channel_id: u64, older: &[PartialMessage]) -> Option<String> {
+        let refresh = Duration::from_secs(config::get().summary_refresh_secs);
+        if let Some((summary, created)) = self.summaries.lock().unwrap().get(&channel_id) {
+            if created.elapsed() < refresh {
+                return Some(summary.clone());
+            }
+        }
+        let request = prompt::summary_request(older, self.pseudonyms().as_deref_mut());
+        let response = match self.chat(request, config::get().sampling).await {
+            Ok(response) => response,
+            Err(why) => {
+                warn!("failed to summarize context for {channel_id}: {why}");
+                return None;
+            }
+        };
+        let summary = response.choices.iter()
+            .filter_map(|x| x.parseable())
+            .find_map(|x| serde_json::from_str::<prompt::Summary>(x).ok())?
+            .summary;
+        debug!("summarized {} messages in {channel_id}: {summary}", older.len());
+        self.summaries.lock().unwrap().insert(channel_id, (summary.clone(), Instant::now()));
+        Some(summary)
+    }
+
+    /// Asks the model about the last of `messages`, with the rest put in `CONTEXT_ORDER` ahead of it,
+    /// using `model` instead of the configured one when given. Nothing is acted on.
+    /// Messages stored for other channels (a thread's parent) only serve as context.
+    /// An empty context, such as a command run in a channel with nothing stored, has
+    /// no message to judge and gives `None` without calling the provider.
+    async fn verdict(&self, messages: Vec<PartialMessage>, model: Option<&str>) -> Option<Verdict> {
+        let messages = order_context(messages, config::get().context_order);
+        let newest = messages.len().checked_sub(1)?;
+        let channel_id = messages[newest].channel_id;
+        let strictness = if messages[newest].new_member {
+            Strictness::Strict
+        }else{
+            self.channel_strictness(channel_id)
+        };
+        let config = config::get();
+        let (summary, messages) = if config.summarize_context && messages.len() > config.summarize_threshold {
+            let (older, recent) = messages.split_at(messages.len() - config.summary_raw_messages);
+            (self.context_summary(channel_id, older).await, recent.to_vec())
+        }else{
+            (None, messages)
+        };
+        let profile = self.channel_profile(channel_id);
+        let (rules, base) = self.prompt_and_sampling();
+        let ai_messages = prompt::build(&messages, &rules, strictness, profile, summary.as_deref(), self.pseudonyms().as_deref_mut());
+        let sampling = strictness.sampling(profile.map_or(base, |x| x.sampling(base)));
+        if let Some(limit) = config.model_context_tokens {
+            let tokens = prompt::estimate_tokens(&ai_messages) + sampling.max_tokens as usize;
+            if tokens * 10 > limit * 9 {
+                warn!("the request for {channel_id} takes ~{tokens} tokens with the completion, close to MODEL_CONTEXT_TOKENS={limit}");
+            }
+        }
+        let started = Instant::now();
+        let response = match model {
+            Some(model) => self.chat_as(model, ai_messages, sampling).await,
+            None => self.chat(ai_messages, sampling).await
+        };
+        let latency = started.elapsed();
+        let (usage, outcome) = match response {
+            Ok(response) => (response.usage, self.outcome(&messages, &response.choices)),
+            Err(why) => (None, Outcome::Failed(why))
+        };
+        Some(Verdict {
+            newest: messages[messages.len() - 1].clone(),
+            judged: messages.iter().filter(|x| x.channel_id == channel_id).map(|x| x.id).collect(),
+            outcome,
+            latency,
+            usage
+        })
+    }
+
+    /// The majority verdict among `choices`, resolved to the message it is about.
+    fn outcome(&self, messages: &[PartialMessage], choices: &[Completion]) -> Outcome {
+        // Truncated choices are dropped, so a response that is all garbage is never acted on.
+        let validations = choices.iter().filter_map(|x| {
+            let Some(content) = x.parseable() else {
+                self.record_parse(Err(format!("cut off or too long: {:?}", truncate(&x.content, ERROR_RESPONSE_LEN))));
+                return None;
+            };
+            Validation::parse(content)
+                .inspect(|_| self.record_parse(Ok(())))
+                .inspect_err(|why| {
+                    warn!("could not parse validation {content:?}: {why}");
+                    let error = format!("could not parse validation {:?}: {why}", truncate(content, ERROR_RESPONSE_LEN));
+                    self.record_parse(Err(error.clone()));
+                    self.record_error(error);
+                })
+                .ok()
+        }).collect::<Vec<Validation>>();
+        let Some(validation) = Validation::majority(validations) else {
+            return Outcome::Unparsed;
+        };
+        validation.outcome(messages, |author| self.resolve_author(author))
+    }
+
+    /// Validates the newest of `messages` and acts on the verdict.
+    async fn ai_request(&self, ctx: &Context, messages: Vec<PartialMessage>) {
+        if self.ai_paused.load(atomic::Ordering::Relaxed) {
+            debug!("AI calls are paused, leaving {} messages unvalidated", messages.len());
+            return;
+        }
+        let Some(verdict) = self.verdict(messages, None).await else {
+            return;
+        };
+        let message = &verdict.newest;
+        if !matches!(verdict.outcome, Outcome::Failed(_)) && self.degraded.swap(false, atomic::Ordering::Relaxed) {
+            info!("the provider recovered, resuming moderation with the model");
+        }
+        if !matches!(verdict.outcome, Outcome::Failed(_) | Outcome::Unparsed) {
+            // The context the model saw has now had a verdict, so `/pending` stops listing it.
+            // Messages that arrived since, or came after an edited one, still wait for their own.
+            self.database.send(definitions::DatabaseMessage::ValidateEntries { channel_id: message.channel_id, ids: verdict.judged });
+        }
+        let (target, reason, severity, confidence) = match verdict.outcome {
+            Outcome::Failed(ProviderError::OverBudget) => {
+                let notify = !std::mem::replace(&mut self.usage.lock().unwrap().notified, true);
+                if notify {
+                    let embed = CreateEmbed::new()
+                        .title("Daily AI budget used up")
+                        .description("Messages are only checked with heuristics until the budget resets. See `/usage`.");
+                    enforcement::notify_mods(ctx, self.log_channel(message.guild_id), embed).await;
+                }
+                if let Some(reason) = heuristics::degraded_reason(&message.content) {
+                    self.flag(ctx, message, &reason, Some(Severity::Medium), None).await;
+                }
+                return;
+            },
+            Outcome::Failed(why) => {
+                error!("{} {why}", self.provider.name());
+                if !self.degraded.swap(true, atomic::Ordering::Relaxed) {
+                    warn!("the provider is unavailable, moderating with heuristics only");
+                }
+                if let Some(reason) = heuristics::degraded_reason(&message.content) {
+                    self.flag(ctx, message, &reason, Some(Severity::Medium), None).await;
+                }
+                return;
+            },
+            Outcome::Unparsed => return,
+            Outcome::Unusable(why) => {
+                warn!("{why}");
+                return;
+            },
+            Outcome::Fine => {
+                debug!("this message is fine");
+                enforcement::react(ctx, message, '✅').await;
+                return;
+            },
+            Outcome::Flagged { target, reason, severity, confidence } => (target, reason, severity, confidence)
+        };
+        if confidence.is_some_and(|x| x < config::get().uncertain_threshold) {
+            match config::get().uncertain_action {
+                UncertainAction::Ignore => {},
+                UncertainAction::Log => info!("not acting on uncertain flag for message {}: {reason}", target.id),
+                UncertainAction::Notify => {
+                    let embed = enforcement::flag_embed(&target, &reason, severity, confidence).title("Uncertain flag, no action taken");
+                    enforcement::notify_mods(ctx, self.log_channel(target.guild_id), embed).await;
+                }
+            }
+            return;
+        }
+        self.flag(ctx, &target, &reason, severity, confidence).await;
+    }
 }
 
 impl Default for Handler {
     fn default() -> Self {
-        let id = std::env::var("APPLICATION_ID").unwrap().parse::<u64>().unwrap();
-        let (sender, receiver) = definitions::Database::new();
-        Self {
-            id,
-            database_connection: (sender, receiver),
-            web_client: reqwest::Client::new()
-        }
+        let config = config::get();
+        let database = DatabaseConnection::new();
+        let profiles = match database.query(definitions::DatabaseMessage::GetChannelProfiles) {
+            Some(definitions::DatabaseResponse::ChannelProfiles(profiles)) => profiles,
+            None => HashMap::new(),
+            Some(_) => unreachable!("GetChannelProfiles is answered with ChannelProfiles")
+        };
+        Self(Arc::new(HandlerState {
+            id: config.application_id,
+            database,
+            web_client: provider::http_client(config),
+            provider: provider::from_config(config),
+            fallback: provider::fallback_from_config(config),
+            model: RwLock::new(config.model.clone()),
+            prompt_snapshot: RwLock::new(None),
+            ai_permits: Semaphore::new(config.max_concurrent_ai),
+            degraded: AtomicBool::new(false),
+            ai_paused: AtomicBool::new(false),
+            usage: Mutex::new(DailyUsage::default()),
+            reactions: Mutex::new(HashMap::new()),
+            names: Mutex::new(HashMap::new()),
+            last_action: Mutex::new(HashMap::new()),
+            strictness: RwLock::new(HashMap::new()),
+            profiles: RwLock::new(profiles),
+            summaries: Mutex::new(HashMap::new()),
+            errors: Mutex::new(VecDeque::new()),
+            parses: Mutex::new(VecDeque::new()),
+            pseudonyms: Mutex::new(Pseudonyms::default())
+        }))
     }
 }
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn message(&self, _ctx: Context, msg: Message) {
-        if msg.author.id.get() == self.id {
+    async fn message(&self, ctx: Context, msg: Message) {
+        let received = Instant::now();
+        if self.ignores(&msg.author) || config::get().command_only {
             return;
         }
+        if msg.guild_id.is_some_and(|x| !self.guild_settings(x.get()).enabled) {
+            return;
+        }
+        metrics::increment(&metrics::get().messages);
 
-        let (sender, receiver) = &self.database_connection;
-        let message = PartialMessage::from(msg);
-        let _ = sender.send(definitions::DatabaseMessage::InsertMessage(message));
-        let _ = sender.send(definitions::DatabaseMessage::GetLatest(20));
-        let messages = receiver.recv().unwrap();
-        self.ai_request(messages).await;
+        let attachments = msg.attachments.clone();
+        let config = config::get();
+        // Only messages people wrote are validated; joins, boosts and pins are system notices.
+        if !matches!(msg.kind, MessageType::Regular | MessageType::InlineReply) {
+            debug!("skipping {:?} message {} in {}", msg.kind, msg.id, msg.channel_id);
+            if config.system_messages == SystemMessages::Store {
+                let message = PartialMessage { status: ValidationStatus::Validated, ..PartialMessage::from(msg) };
+                self.database.send(definitions::DatabaseMessage::InsertMessage(message));
+            }
+            return;
+        }
+        if config.max_message_age_secs.is_some_and(|x| Timestamp::now().unix_timestamp() - msg.timestamp.unix_timestamp() > x) {
+            debug!("skipping validation of stale message {} in {}", msg.id, msg.channel_id);
+            if config.store_stale_messages {
+                // Marked as reviewed so later verdicts never act on it.
+                let message = PartialMessage { status: ValidationStatus::Validated, ..PartialMessage::from(msg) };
+                self.database.send(definitions::DatabaseMessage::InsertMessage(message));
+            }
+            return;
+        }
+        if let Some(content) = self.bypassed_content(&msg) {
+            debug!("message {} in {} bypasses moderation", msg.id, msg.channel_id);
+            // Marked as reviewed so later verdicts never act on it.
+            let content = stored_content(content);
+            let message = PartialMessage { content, status: ValidationStatus::Validated, ..PartialMessage::from(msg) };
+            self.database.send(definitions::DatabaseMessage::InsertMessage(message));
+            return;
+        }
+        let mass_mention = match heuristics::mass_mention(&msg) {
+            Some(_) if self.can_mention_everyone(&ctx, &msg).await => None,
+            mass_mention => mass_mention
+        };
+        let new_member = self.is_new_member(&ctx, &msg).await;
+        let forwarded = self.forwarded_content(&ctx, &msg).await;
+        let mut message = PartialMessage { forwarded, ..PartialMessage::from(msg) };
+        let channel_id = message.channel_id;
+        if new_member {
+            message.new_member = true;
+            message.notes.push("the author joined this server very recently".into());
+        }
+        let link_verdict = heuristics::link_verdict(&message.content);
+        if let Some(LinkVerdict::Unknown(domain)) = &link_verdict {
+            message.notes.push(format!("links to {domain}, which is not an allowed domain"));
+        }
+        let prefilter = heuristics::prefilter(&message.content);
+        let escalated = matches!(&prefilter, Some((x, _)) if x.action == PrefilterAction::Escalate);
+        if let Some((prefilter, matched)) = prefilter.as_ref().filter(|_| escalated) {
+            message.notes.push(format!("{matched:?} matches the moderators' pattern {}", prefilter.pattern));
+        }
+        // Summarization needs the older messages that would otherwise be cut off.
+        let n_latest = if config.summarize_context { u8::MAX } else { PROMPT_CONTEXT };
+        let (mut messages, wave) = self.insert_and_get_latest(message.clone(), n_latest);
+        if !wave.is_empty() {
+            info!("{} copies of message {} form a spam wave", wave.len(), message.id);
+            for copy in &wave {
+                self.flag(&ctx, copy, "Part of a coordinated spam wave of identical messages", Some(Severity::High), None).await;
+            }
+            return;
+        }
+        match link_verdict {
+            Some(LinkVerdict::Blocked(domain)) => {
+                self.flag(&ctx, &message, &format!("Links to {domain} are not allowed"), Some(Severity::High), None).await;
+                return;
+            },
+            Some(LinkVerdict::Unknown(domain)) if config::get().unknown_domain_action == UnknownDomainAction::Flag => {
+                self.flag(&ctx, &message, &format!("Links to {domain} are not on the allowlist"), Some(Severity::Medium), None).await;
+                return;
+            },
+            _ => {}
+        }
+        if let Some((prefilter, _)) = prefilter.filter(|_| !escalated) {
+            self.flag(&ctx, &message, &format!("Matches the blocked pattern {}", prefilter.pattern), Some(Severity::High), None).await;
+            return;
+        }
+        if let Some(mass_mention) = mass_mention {
+            let reason = match mass_mention {
+                MassMention::Everyone => "Pinging @everyone or @here is not allowed".into(),
+                MassMention::Many(mentions) => format!("Mentioned {mentions} users and roles at once")
+            };
+            self.flag(&ctx, &message, &reason, Some(Severity::High), None).await;
+            return;
+        }
+        if let Some(language) = heuristics::disallowed_language(&message.content) {
+            self.flag(&ctx, &message, &format!("Messages in {} are not allowed here", language.eng_name()), Some(Severity::Low), None).await;
+            return;
+        }
+        if let Some(attachment) = heuristics::blocked_attachment(&attachments) {
+            self.flag(&ctx, &message, &format!("Attachment {} has a blocked file type", attachment.filename), Some(Severity::High), None).await;
+            return;
+        }
+        if let Some((attachment, score)) = heuristics::nsfw_attachment(&self.web_client, &attachments).await {
+            self.flag(&ctx, &message, &format!("Attachment {} was classified as NSFW (score {score:.2})", attachment.filename), Some(Severity::High), None).await;
+            return;
+        }
+        if config.include_thread_parent {
+            let mut parent = self.thread_parent_context(&ctx, ChannelId::new(channel_id)).await;
+            parent.append(&mut messages);
+            messages = parent;
+        }
+        // New members and escalated matches are always checked, however little context there is.
+        if config.sparse_context_action.skips(messages.len(), config.min_context, message.new_member || escalated) {
+            debug!("skipping validation in {channel_id}, only {} messages of context", messages.len());
+            return;
+        }
+        self.ai_request(&ctx, messages).await;
+        metrics::get().message_latency.record(received.elapsed());
+    }
+
+    async fn message_update(&self, ctx: Context, _old_if_available: Option<Message>, _new: Option<Message>, event: MessageUpdateEvent) {
+        // Embed unfurls also arrive as updates, without content.
+        let Some(content) = event.content else {
+            return;
+        };
+        if event.author.is_some_and(|x| self.ignores(&x)) || config::get().command_only {
+            return;
+        }
+        if event.guild_id.is_some_and(|x| !self.guild_settings(x.get()).enabled) {
+            return;
+        }
+        let channel_id = event.channel_id.get();
+        let Some(edited) = self.edit_message(channel_id, event.id.get(), stored_content(&content)) else {
+            return;
+        };
+        // The edit is judged as the newest message, against what came before it.
+        let mut messages = self.get_latest(channel_id, u8::MAX);
+        messages.retain(|x| x.id <= edited.id);
+        let messages = messages.split_off(messages.len().saturating_sub(PROMPT_CONTEXT as usize));
+        self.ai_request(&ctx, messages).await;
+    }
+
+    async fn guild_member_update(&self, ctx: Context, _old_if_available: Option<Member>, _new: Option<Member>, event: GuildMemberUpdateEvent) {
+        if !config::get().moderate_nicknames || self.ignores(&event.user) {
+            return;
+        }
+        if !self.guild_settings(event.guild_id.get()).enabled {
+            return;
+        }
+        self.check_names(&ctx, &event).await;
+    }
+
+    async fn reaction_add(&self, _ctx: Context, reaction: Reaction) {
+        let Some(threshold) = config::get().reaction_spam_threshold else {
+            return;
+        };
+        let Some(user_id) = reaction.user_id.map(|x| x.get()) else {
+            return;
+        };
+        if user_id == self.id {
+            return;
+        }
+        if self.is_reaction_spam(user_id, threshold) {
+            warn!("user {user_id} is spamming reactions in channel {}", reaction.channel_id);
+        }
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!("{} is connected", ready.user.name);
+        if let Err(why) = serenity::all::Command::set_global_commands(&ctx.http, commands::all()).await {
+            error!("failed to register commands: {why}");
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Command(command) = interaction {
+            commands::run(self, &ctx, &command).await;
+        }
     }
 }
 
 
 
 fn start_env() {
-    dotenvy::dotenv().unwrap();
+    // Containers usually pass the environment directly, so a missing .env is fine.
+    let dotenv = dotenvy::dotenv();
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+    match dotenv {
+        Ok(path) => info!("loaded environment from {}", path.display()),
+        Err(why) if why.not_found() => info!("no .env file found, using the process environment"),
+        Err(why) => {
+            error!("failed to load .env: {why}");
+            std::process::exit(1);
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
     start_env();
-    let token = std::env::var("API_KEY").unwrap();
-    let mut client = serenity::Client::builder(token, GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT)
+    match config::Config::from_env() {
+        Ok(config) => config::init(config),
+        Err(why) => {
+            error!("{why}");
+            std::process::exit(1);
+        }
+    }
+    let summary = config::get().summary().into_iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<String>>()
+        .join(" ");
+    info!(config = %summary, "loaded configuration");
+    if let Some(limit) = config::get().model_context_tokens {
+        let worst_case = prompt::worst_case_tokens(config::get());
+        if worst_case > limit {
+            warn!("requests may take up to ~{worst_case} tokens, more than MODEL_CONTEXT_TOKENS={limit}; lower CONTEXT_SIZE or set MAX_STORED_CONTENT_LEN");
+        }
+    }
+    if let Some(addr) = config::get().metrics_addr {
+        tokio::spawn(metrics::serve(addr));
+    }
+    if let Some(addr) = config::get().statsd_addr {
+        tokio::spawn(metrics::send_statsd(addr));
+    }
+    let token = &config::get().api_key;
+    let mut intents = GatewayIntents::GUILDS;
+    if !config::get().command_only {
+        intents |= GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    }
+    if config::get().reaction_spam_threshold.is_some() {
+        intents |= GatewayIntents::GUILD_MESSAGE_REACTIONS;
+    }
+    if config::get().moderate_nicknames {
+        intents |= GatewayIntents::GUILD_MEMBERS;
+    }
+    // Serenity shares this one handler, and so one database thread, across every shard.
+    let mut client = serenity::Client::builder(token, intents)
         .event_handler(Handler::default())
         .await
         .unwrap();
     client.start().await.unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verdict_without_context_is_none() {
+        config::init_for_tests();
+        assert!(Handler::default().verdict(Vec::new(), None).await.is_none());
+    }
+
+    #[test]
+    fn ordering_keeps_the_judged_message_last() {
+        for order in [ContextOrder::Timestamp, ContextOrder::Id] {
+            // The thread's messages follow their parent's, one of which is newer than the thread message.
+            let messages = vec![
+                PartialMessage::new(1, 20, 1, "parent".into(), ValidationStatus::Validated, 1),
+                PartialMessage::new(4, 20, 1, "newer parent".into(), ValidationStatus::Validated, 4),
+                PartialMessage::new(2, 10, 2, "thread".into(), ValidationStatus::Validated, 2),
+                PartialMessage::new(3, 10, 2, "judged".into(), ValidationStatus::NotValidated, 3)
+            ];
+            let ids = order_context(messages, order).into_iter().map(|x| x.id).collect::<Vec<u64>>();
+            assert_eq!(ids, [1, 2, 4, 3]);
+        }
+    }
+
+    fn validation(user_id: Option<u64>, reason: Option<&str>) -> Validation {
+        Validation {
+            user_id: user_id.map(Author::Id),
+            reason: reason.map(String::from),
+            confidence: None,
+            severity: None
+        }
+    }
+
+    fn resolve_id(author: &Author) -> Option<u64> {
+        match author {
+            Author::Id(user_id) => Some(*user_id),
+            Author::Pseudonym(_) => None
+        }
+    }
+
+    /// Two unreviewed messages by users 1 and 2, after a reviewed one by user 3, in channel 10.
+    fn context() -> Vec<PartialMessage> {
+        vec![
+            PartialMessage::new(1, 10, 3, "earlier".into(), ValidationStatus::Validated, 0),
+            PartialMessage::new(2, 10, 1, "rude".into(), ValidationStatus::NotValidated, 1),
+            PartialMessage::new(3, 10, 2, "newest".into(), ValidationStatus::NotValidated, 2)
+        ]
+    }
+
+    fn flagged_id(outcome: Outcome) -> Option<u64> {
+        match outcome {
+            Outcome::Flagged { target, .. } => Some(target.id),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn named_user_with_reason_flags_their_unreviewed_message() {
+        assert_eq!(flagged_id(validation(Some(1), Some("insult")).outcome(&context(), resolve_id)), Some(2));
+    }
+
+    #[test]
+    fn named_user_without_unreviewed_message_is_unusable() {
+        let outcome = validation(Some(3), Some("insult")).outcome(&context(), resolve_id);
+        assert!(matches!(outcome, Outcome::Unusable(_)));
+    }
+
+    #[test]
+    fn unknown_author_is_unusable() {
+        let validation = Validation { user_id: Some(Author::Pseudonym("User Z".into())), ..validation(None, Some("insult")) };
+        assert!(matches!(validation.outcome(&context(), resolve_id), Outcome::Unusable(_)));
+    }
+
+    #[test]
+    fn reason_without_user_flags_the_newest_message() {
+        assert_eq!(flagged_id(validation(None, Some("insult")).outcome(&context(), resolve_id)), Some(3));
+    }
+
+    #[test]
+    fn user_without_reason_is_unusable() {
+        assert!(matches!(validation(Some(1), None).outcome(&context(), resolve_id), Outcome::Unusable(_)));
+    }
+
+    #[test]
+    fn neither_user_nor_reason_is_fine() {
+        assert!(matches!(validation(None, None).outcome(&context(), resolve_id), Outcome::Fine));
+    }
+}
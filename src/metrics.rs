@@ -0,0 +1,224 @@
+use std::{collections::VecDeque, net::SocketAddr, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Mutex}, time::Duration};
+
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, UdpSocket}};
+use tracing::{error, info, warn};
+
+/// Counters since startup, rendered in the Prometheus text format at `METRICS_ADDR`
+/// and sent to `STATSD_ADDR`.
+pub struct Metrics {
+    /// Messages that reached moderation, after ignored authors and disabled guilds.
+    pub messages: AtomicU64,
+    pub ai_requests: AtomicU64,
+    pub provider_errors: AtomicU64,
+    /// Choices that stopped at `MAX_TOKENS` or exceeded the parse limit and were ignored.
+    pub truncated_responses: AtomicU64,
+    pub flags: AtomicU64,
+    /// From entering the `message` handler until its verdict is acted on.
+    pub message_latency: Latency,
+    /// One request/response pair with the database thread.
+    pub database_latency: Latency,
+    /// One provider request, not counting the wait for a `MAX_CONCURRENT_AI` permit.
+    pub provider_latency: Latency,
+}
+
+static METRICS: Metrics = Metrics {
+    messages: AtomicU64::new(0),
+    ai_requests: AtomicU64::new(0),
+    provider_errors: AtomicU64::new(0),
+    truncated_responses: AtomicU64::new(0),
+    flags: AtomicU64::new(0),
+    message_latency: Latency::new(),
+    database_latency: Latency::new(),
+    provider_latency: Latency::new(),
+};
+
+/// How many recent samples each [`Latency`] keeps for its percentiles, and at most
+/// how many it holds for the next StatsD flush.
+const LATENCY_SAMPLES: usize = 1024;
+/// How often counters and timings are sent to `STATSD_ADDR`.
+const STATSD_INTERVAL: Duration = Duration::from_secs(10);
+/// Keeps each StatsD datagram under a typical MTU.
+const MAX_DATAGRAM_LEN: usize = 1432;
+
+/// Set once the StatsD sink runs, so timings are only held for it when something sends them.
+static STATSD: AtomicBool = AtomicBool::new(false);
+
+/// The most recent durations of something, summarized as percentiles.
+pub struct Latency {
+    samples: Mutex<VecDeque<Duration>>,
+    /// Recorded since the last StatsD flush.
+    unsent: Mutex<Vec<Duration>>
+}
+
+impl Latency {
+    const fn new() -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::new()),
+            unsent: Mutex::new(Vec::new())
+        }
+    }
+
+    pub fn record(&self, duration: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(duration);
+        if STATSD.load(Ordering::Relaxed) {
+            let mut unsent = self.unsent.lock().unwrap();
+            if unsent.len() < LATENCY_SAMPLES {
+                unsent.push(duration);
+            }
+        }
+    }
+
+    /// The 50th, 90th and 99th percentiles, or `None` before the first sample.
+    pub fn percentiles(&self) -> Option<[(f64, Duration); 3]> {
+        let mut samples = self.samples.lock().unwrap().iter().copied().collect::<Vec<Duration>>();
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let at = |quantile: f64| (quantile, samples[((samples.len() - 1) as f64 * quantile).round() as usize]);
+        Some([at(0.5), at(0.9), at(0.99)])
+    }
+}
+
+pub fn get() -> &'static Metrics {
+    &METRICS
+}
+
+pub fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+impl Metrics {
+    fn counters(&self) -> [(&'static str, &'static str, &AtomicU64); 5] {
+        [
+            ("messages_total", "Messages that reached moderation.", &self.messages),
+            ("ai_requests_total", "Requests sent to the AI provider.", &self.ai_requests),
+            ("provider_errors_total", "Provider requests that failed.", &self.provider_errors),
+            ("truncated_responses_total", "Response choices ignored for being truncated or oversized.", &self.truncated_responses),
+            ("flags_total", "Messages flagged.", &self.flags),
+        ]
+    }
+
+    pub fn latencies(&self) -> [(&'static str, &Latency); 3] {
+        [
+            ("message", &self.message_latency),
+            ("database", &self.database_latency),
+            ("provider", &self.provider_latency),
+        ]
+    }
+
+    pub fn render(&self) -> String {
+        let counters = self.counters().iter()
+            .map(|(name, help, value)| {
+                format!("# HELP discord_bot_{name} {help}\n# TYPE discord_bot_{name} counter\ndiscord_bot_{name} {}\n", value.load(Ordering::Relaxed))
+            })
+            .collect::<String>();
+        let latencies = self.latencies().iter()
+            .map(|(name, latency)| {
+                let quantiles = latency.percentiles().into_iter().flatten()
+                    .map(|(quantile, value)| format!("discord_bot_{name}_latency_seconds{{quantile=\"{quantile}\"}} {}\n", value.as_secs_f64()))
+                    .collect::<String>();
+                format!("# TYPE discord_bot_{name}_latency_seconds summary\n{quantiles}")
+            })
+            .collect::<String>();
+        counters + &latencies
+    }
+}
+
+/// The StatsD lines for everything since the last flush: counter increments since
+/// `sent`, which is updated, and a timer per unsent latency sample.
+fn statsd_lines(metrics: &Metrics, sent: &mut [u64; 5]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for ((name, _, value), sent) in metrics.counters().iter().zip(sent.iter_mut()) {
+        let value = value.load(Ordering::Relaxed);
+        if value > *sent {
+            lines.push(format!("discord_bot.{}:{}|c", name.trim_end_matches("_total"), value - *sent));
+            *sent = value;
+        }
+    }
+    for (name, latency) in metrics.latencies() {
+        let unsent = std::mem::take(&mut *latency.unsent.lock().unwrap());
+        lines.extend(unsent.iter().map(|x| format!("discord_bot.{name}_latency:{}|ms", x.as_secs_f64() * 1000.0)));
+    }
+    lines
+}
+
+/// Sends counters and timers to the StatsD or DogStatsD agent at `addr` over UDP every `STATSD_INTERVAL`.
+pub async fn send_statsd(addr: SocketAddr) {
+    let local: SocketAddr = if addr.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+    let socket = match UdpSocket::bind(local).await {
+        Ok(socket) => socket,
+        Err(why) => {
+            error!("could not open a socket for StatsD: {why}");
+            return;
+        }
+    };
+    if let Err(why) = socket.connect(addr).await {
+        error!("could not reach StatsD at {addr}: {why}");
+        return;
+    }
+    info!("sending metrics to StatsD at {addr}");
+    STATSD.store(true, Ordering::Relaxed);
+    let mut sent = [0; 5];
+    let mut interval = tokio::time::interval(STATSD_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut datagram = String::new();
+        for line in statsd_lines(get(), &mut sent) {
+            if !datagram.is_empty() && datagram.len() + line.len() + 1 > MAX_DATAGRAM_LEN {
+                send_datagram(&socket, &datagram).await;
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(&line);
+        }
+        if !datagram.is_empty() {
+            send_datagram(&socket, &datagram).await;
+        }
+    }
+}
+
+/// UDP delivery is best-effort, so a failed send is only logged and its metrics are lost.
+async fn send_datagram(socket: &UdpSocket, datagram: &str) {
+    if let Err(why) = socket.send(datagram.as_bytes()).await {
+        warn!("failed to send metrics to StatsD: {why}");
+    }
+}
+
+/// Answers every connection on `addr` with the current metrics, whatever the request path.
+pub async fn serve(addr: SocketAddr) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(why) => {
+            error!("could not bind the metrics endpoint to {addr}: {why}");
+            return;
+        }
+    };
+    info!("serving metrics on {addr}");
+    loop {
+        let (mut stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(why) => {
+                warn!("failed to accept a metrics connection: {why}");
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            // The request itself is irrelevant, it only has to be read before answering.
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let body = get().render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
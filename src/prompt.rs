@@ -0,0 +1,472 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::{config::{self, Config, ContextPacking, Profile, PromptFormat, Sampling}, AIMessage, PartialMessage, Validation, ValidationStatus, PROMPT_CONTEXT, THREAD_PARENT_CONTEXT};
+
+/// Characters per token assumed when estimating prompt sizes, which is about right for English.
+const CHARS_PER_TOKEN: usize = 4;
+/// Message length assumed for the token estimate when `MAX_STORED_CONTENT_LEN` is unset, Discord's limit with Nitro.
+const MAX_MESSAGE_LEN: usize = 4000;
+/// Labels and fields around each message's content in the prompt.
+const MESSAGE_OVERHEAD: usize = 150;
+
+/// Prefixed to the newest message so the model judges it and treats the rest as context.
+const NEW_MESSAGE_MARKER: &str = ">>> NEW MESSAGE TO EVALUATE:";
+
+/// How harshly a channel is moderated, set per channel with `/strictness`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strictness {
+    Lenient,
+    #[default]
+    Normal,
+    Strict
+}
+
+impl FromStr for Strictness {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lenient" => Ok(Self::Lenient),
+            "normal" => Ok(Self::Normal),
+            "strict" => Ok(Self::Strict),
+            _ => Err(())
+        }
+    }
+}
+
+impl Strictness {
+    fn instruction(self) -> Option<&'static str> {
+        match self {
+            Self::Lenient => Some("Moderation in this channel is lenient: only flag clear, unambiguous violations and give borderline messages the benefit of the doubt."),
+            Self::Normal => None,
+            Self::Strict => Some("Moderation in this channel is strict: flag borderline messages as well as clear violations.")
+        }
+    }
+
+    /// Strict channels sample with a low temperature so verdicts stay consistent.
+    pub fn sampling(self, base: Sampling) -> Sampling {
+        match self {
+            Self::Strict => Sampling { temperature: base.temperature.min(0.2), ..base },
+            Self::Lenient | Self::Normal => base
+        }
+    }
+}
+
+/// `rules`, normally `SYSTEM_PROMPT`, wrapped in `PROMPT_PREFIX` and `PROMPT_SUFFIX` when they are set,
+/// followed by the channel's profile and strictness, the compact layout and context packing when enabled, the JSON
+/// contract the response is parsed with, and the `PROMPT_LANGUAGE` for reasons.
+pub fn system_prompt(rules: &str, strictness: Strictness, profile: Option<&Profile>) -> String {
+    let config = config::get();
+    let contract = json_contract();
+    let layout = compact_layout(config);
+    let packing = packing_layout(config);
+    let language = config.prompt_language.as_ref()
+        .map(|x| format!("Write the reason in {x}, whatever language the messages are in. Keep the JSON keys and severity values in English."));
+    [config.prompt_prefix.as_deref(), Some(rules), config.prompt_suffix.as_deref(), profile.and_then(|x| x.prompt.as_deref()), strictness.instruction(), layout.as_deref(), packing.as_deref(), Some(contract.as_str()), language.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<&str>>()
+        .join("\n\n")
+}
+
+fn json_contract() -> String {
+    let config = config::get();
+    let example = Validation::example_json();
+    let (user_id, reason) = (config.validation_field("user_id"), config.validation_field("reason"));
+    let (confidence, severity) = (config.validation_field("confidence"), config.validation_field("severity"));
+    format!(
+        "Respond with a single JSON object and nothing else, shaped like this example:\n{example}\n\
+        {user_id} is the AUTHOR of the message that breaks the rules. \
+        {reason} explains the violation; when no rule is broken, set {reason} and {user_id} to null. \
+        {confidence} is how sure you are of the verdict, from 0 to 1. \
+        {severity} is low, medium or high for a violation, and null otherwise. \
+        Messages that were PREVIOUSLY_REVIEWED were already judged; use them only as context and never flag them again. \
+        An edited message shows what it said before the edit; judge its current content, but watch for harmless messages edited into rule-breaking ones."
+    )
+}
+
+/// Stable stand-ins for user ids under `ANONYMIZE_USERS`, kept for as long as the bot runs.
+#[derive(Default)]
+pub struct Pseudonyms {
+    names: HashMap<u64, String>,
+    ids: HashMap<String, u64>
+}
+
+impl Pseudonyms {
+    /// The pseudonym of `user_id`, assigning the next free one on first sight.
+    pub fn name(&mut self, user_id: u64) -> String {
+        if let Some(name) = self.names.get(&user_id) {
+            return name.clone();
+        }
+        let name = format!("User {}", letters(self.names.len()));
+        self.names.insert(user_id, name.clone());
+        self.ids.insert(name.to_ascii_uppercase(), user_id);
+        name
+    }
+
+    /// The user behind a pseudonym echoed by the model.
+    pub fn resolve(&self, name: &str) -> Option<u64> {
+        self.ids.get(&name.trim().to_ascii_uppercase()).copied()
+    }
+}
+
+/// A, B, ..., Z, AA, AB, ... for `index` 0, 1, ...
+fn letters(mut index: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(char::from(b'A' + (index % 26) as u8));
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    letters.into_iter().rev().collect()
+}
+
+/// How an author is named in the prompt: their id, or their pseudonym under `ANONYMIZE_USERS`.
+fn author_label(pseudonyms: &mut Option<&mut Pseudonyms>, user_id: u64) -> String {
+    match pseudonyms {
+        Some(pseudonyms) => pseudonyms.name(user_id),
+        None => user_id.to_string()
+    }
+}
+
+/// The conversation asking the model to validate the newest of `messages`, which are ordered oldest to newest.
+/// `summary` condenses context older than `messages`, when `SUMMARIZE_CONTEXT` produced one.
+/// Authors are named by their pseudonyms when `pseudonyms` is given.
+/// Under `CONTEXT_PACKING=single` everything after the system prompt is sent as one user message.
+/// The system prompt is added last from `rules`, as [`with_instructions`] lays it out.
+/// Empty `messages` leave nothing to evaluate, so only the instructions and `summary` are sent.
+pub fn build(messages: &[PartialMessage], rules: &str, strictness: Strictness, profile: Option<&Profile>, summary: Option<&str>, mut pseudonyms: Option<&mut Pseudonyms>) -> Vec<AIMessage> {
+    let newest = messages.len().saturating_sub(1);
+    let channel_id = messages.last().map(|x| x.channel_id);
+    let mut ai_messages = Vec::new();
+    if let Some(summary) = summary {
+        ai_messages.push(AIMessage::new(Some(format!("CONTEXT SUMMARY OF EARLIER MESSAGES:\n{summary}")), "user".into()));
+    }
+    ai_messages.extend(messages.iter().enumerate().map(|(i, x)| {
+        let mut content = format_message(x, &mut pseudonyms);
+        if Some(x.channel_id) != channel_id {
+            content = format!("FROM PARENT CHANNEL\n{content}");
+        }
+        if i == newest {
+            content = format!("{NEW_MESSAGE_MARKER}\n{content}");
+            if messages.len() < config::get().min_context {
+                content = format!("{}\n{content}", sparse_context_note(newest));
+            }
+        }
+        AIMessage::new(Some(content), "user".into())
+    }));
+    let config = config::get();
+    let ai_messages = pack(ai_messages, messages.len(), config.context_packing, &config.context_separator);
+    with_instructions(system_prompt(rules, strictness, profile), ai_messages)
+}
+
+/// Lays `ai_messages` out for `packing`. Under [`ContextPacking::Single`] they become one user message,
+/// where the last `chat_messages` are tagged `[MESSAGE n]` and everything is split by `separator` lines.
+fn pack(mut ai_messages: Vec<AIMessage>, chat_messages: usize, packing: ContextPacking, separator: &str) -> Vec<AIMessage> {
+    if packing == ContextPacking::Separate || ai_messages.is_empty() {
+        return ai_messages;
+    }
+    let separator = format!("\n{separator}\n");
+    let first = ai_messages.len() - chat_messages;
+    let tagged = ai_messages.drain(first..)
+        .enumerate()
+        .map(|(i, x)| format!("[MESSAGE {}]\n{}", i + 1, x.content.unwrap_or_default()))
+        .collect::<Vec<String>>();
+    // The summary, if any, leads the packed message untagged since it is not a chat message.
+    let packed = ai_messages.drain(..)
+        .filter_map(|x| x.content)
+        .chain(tagged)
+        .collect::<Vec<String>>()
+        .join(&separator);
+    vec![AIMessage::new(Some(packed), "user".into())]
+}
+
+/// Puts `instructions` ahead of `messages` in an `INSTRUCTION_ROLE` message, or under
+/// `FOLD_INSTRUCTIONS` at the top of the first user message, for providers that reject or
+/// ignore system prompts.
+pub fn with_instructions(instructions: String, messages: Vec<AIMessage>) -> Vec<AIMessage> {
+    let config = config::get();
+    place_instructions(instructions, messages, &config.instruction_role, config.fold_instructions)
+}
+
+/// [`with_instructions`] for a given `role` and `fold`. Folding with no user message to fold
+/// into leads with the instructions as a user message of their own.
+fn place_instructions(instructions: String, mut messages: Vec<AIMessage>, role: &str, fold: bool) -> Vec<AIMessage> {
+    if !fold {
+        messages.insert(0, AIMessage::new(Some(instructions), role.into()));
+        return messages;
+    }
+    match messages.iter_mut().find(|x| x.role == "user") {
+        Some(first) => {
+            first.content = Some(match first.content.take() {
+                Some(content) => format!("{instructions}\n\n{content}"),
+                None => instructions
+            });
+        },
+        None => messages.insert(0, AIMessage::new(Some(instructions), "user".into()))
+    }
+    messages
+}
+
+/// A rough token count of `ai_messages`, enough to notice prompts nearing `MODEL_CONTEXT_TOKENS`.
+pub fn estimate_tokens(ai_messages: &[AIMessage]) -> usize {
+    ai_messages.iter()
+        .map(|x| x.content.as_deref().map_or(0, |x| x.chars().count()) + MESSAGE_OVERHEAD)
+        .sum::<usize>() / CHARS_PER_TOKEN
+}
+
+/// The most tokens a request can take with the current config, counting the completion,
+/// assuming every message in the context and the one it replies to are as long as possible.
+pub fn worst_case_tokens(config: &Config) -> usize {
+    let mut messages = config.context_size.min(PROMPT_CONTEXT as usize);
+    if config.include_thread_parent {
+        messages += THREAD_PARENT_CONTEXT as usize;
+    }
+    let message_len = config.max_stored_content_len.unwrap_or(MAX_MESSAGE_LEN) * 2 + MESSAGE_OVERHEAD;
+    let profile = config.profiles.iter().max_by_key(|x| x.prompt.as_ref().map_or(0, String::len));
+    let system = system_prompt(&config.system_prompt, Strictness::Strict, profile).chars().count();
+    (system + messages * message_len) / CHARS_PER_TOKEN + config.sampling.max_tokens as usize
+}
+
+#[derive(Deserialize)]
+pub struct Summary {
+    pub summary: String
+}
+
+/// Asks the model to condense `messages` into a [`Summary`] for `SUMMARIZE_CONTEXT`.
+pub fn summary_request(messages: &[PartialMessage], mut pseudonyms: Option<&mut Pseudonyms>) -> Vec<AIMessage> {
+    let system = "Summarize the following chat messages in a few sentences for a moderator who needs the context \
+        of the conversation: who is talking, about what, and any tension or rule-breaking. \
+        Respond with a single JSON object and nothing else, shaped like this example:\n\
+        {\"summary\":\"the summary\"}";
+    let transcript = messages.iter()
+        .map(|x| format!("AUTHOR {}: {}", author_label(&mut pseudonyms, x.author_id), x.content))
+        .collect::<Vec<String>>()
+        .join("\n");
+    with_instructions(system.into(), vec![AIMessage::new(Some(transcript), "user".into())])
+}
+
+fn sparse_context_note(earlier: usize) -> String {
+    match earlier {
+        0 => "NOTE: no earlier messages from this channel are available, judge this message on its own.".into(),
+        1 => "NOTE: only 1 earlier message from this channel is available, judge this message mostly on its own.".into(),
+        n => format!("NOTE: only {n} earlier messages from this channel are available, judge this message mostly on its own.")
+    }
+}
+
+/// Explains the `PROMPT_FORMAT=compact` layout, which the model can't infer on its own.
+fn compact_layout(config: &Config) -> Option<String> {
+    if config.prompt_format != PromptFormat::Compact {
+        return None;
+    }
+    let d = &config.prompt_delimiter;
+    Some(format!(
+        "Messages use a compact layout: AUTHOR{d}PREVIOUSLY_REVIEWED (1 or 0){d}CONTENT, \
+        optionally followed by {d}REPLY_TO=author:content, {d}STICKERS=names, {d}NOTES=notes, {d}EDITED_FROM=content before the edit, {d}POLL=question/answers, {d}FORWARDED=forwarded content and {d}COMPONENTS=button and menu labels."
+    ))
+}
+
+/// Explains the `CONTEXT_PACKING=single` layout, so the model reads the packed message as a conversation.
+fn packing_layout(config: &Config) -> Option<String> {
+    if config.context_packing != ContextPacking::Single {
+        return None;
+    }
+    Some(format!(
+        "The conversation is given as one message. Each chat message in it starts with a [MESSAGE n] tag, \
+        and consecutive chat messages are separated by a line containing only {}.",
+        config.context_separator
+    ))
+}
+
+fn format_message(message: &PartialMessage, pseudonyms: &mut Option<&mut Pseudonyms>) -> String {
+    let config = config::get();
+    if config.prompt_format == PromptFormat::Compact {
+        return format_compact(message, &config.prompt_delimiter, pseudonyms);
+    }
+    let reviewed = message.status == ValidationStatus::Validated;
+    let author = author_label(pseudonyms, message.author_id);
+    let mut content = format!("AUTHOR: {author}\nCONTENT: {}\nPREVIOUSLY_REVIEWED: {reviewed}", message.content);
+    if let Some(reply_to) = &message.reply_to {
+        content = format!("IN REPLY TO AUTHOR {}: {}\n{content}", author_label(pseudonyms, reply_to.author_id), reply_to.content);
+    }
+    if !message.stickers.is_empty() {
+        content = format!("{content}\nSTICKERS: {}", message.stickers.join(", "));
+    }
+    if !message.notes.is_empty() {
+        content = format!("{content}\nNOTES: {}", message.notes.join("; "));
+    }
+    if let Some(previous) = &message.previous_content {
+        content = format!("{content}\nEDITED, CONTENT BEFORE THE EDIT: {previous}");
+    }
+    if let Some(poll) = &message.poll {
+        content = format!("{content}\nPOLL QUESTION: {}\nPOLL ANSWERS: {}", poll.question, poll.answers.join("; "));
+    }
+    for forwarded in &message.forwarded {
+        content = format!("{content}\nFORWARDED BY THE AUTHOR: {forwarded}");
+    }
+    if !message.components.is_empty() {
+        content = format!("{content}\nBUTTON AND MENU LABELS: {}", message.components.join("; "));
+    }
+    content
+}
+
+fn format_compact(message: &PartialMessage, d: &str, pseudonyms: &mut Option<&mut Pseudonyms>) -> String {
+    let reviewed = u8::from(message.status == ValidationStatus::Validated);
+    let author = author_label(pseudonyms, message.author_id);
+    let mut content = format!("{author}{d}{reviewed}{d}{}", message.content);
+    if let Some(reply_to) = &message.reply_to {
+        content = format!("{content}{d}REPLY_TO={}:{}", author_label(pseudonyms, reply_to.author_id), reply_to.content);
+    }
+    if !message.stickers.is_empty() {
+        content = format!("{content}{d}STICKERS={}", message.stickers.join(","));
+    }
+    if !message.notes.is_empty() {
+        content = format!("{content}{d}NOTES={}", message.notes.join("; "));
+    }
+    if let Some(previous) = &message.previous_content {
+        content = format!("{content}{d}EDITED_FROM={previous}");
+    }
+    if let Some(poll) = &message.poll {
+        content = format!("{content}{d}POLL={}/{}", poll.question, poll.answers.join("/"));
+    }
+    for forwarded in &message.forwarded {
+        content = format!("{content}{d}FORWARDED={forwarded}");
+    }
+    if !message.components.is_empty() {
+        content = format!("{content}{d}COMPONENTS={}", message.components.join("; "));
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_without_messages_sends_only_the_instructions() {
+        config::init_for_tests();
+        let ai_messages = build(&[], "Flag insults.", Strictness::Normal, None, None, None);
+        assert_eq!(ai_messages.len(), 1);
+        assert_eq!(ai_messages[0].role, "system");
+        assert!(ai_messages[0].content.as_deref().is_some_and(|x| x.starts_with("Flag insults.")));
+    }
+
+    fn context(len: usize) -> Vec<PartialMessage> {
+        (0..len as u64).map(|i| PartialMessage::new(i, 10, 100 + i, format!("message {i}"), ValidationStatus::NotValidated, i as i64)).collect()
+    }
+
+    fn newest_content(ai_messages: &[AIMessage]) -> &str {
+        ai_messages.last().and_then(|x| x.content.as_deref()).unwrap_or_default()
+    }
+
+    #[test]
+    fn sparse_context_is_noted_below_min_context() {
+        let min_context = config::init_for_tests().min_context;
+        // Nothing stored leaves no message to note anything on.
+        assert!(!newest_content(&build(&[], "Flag insults.", Strictness::Normal, None, None, None)).contains("NOTE:"));
+        for stored in [1, 2, min_context - 1] {
+            let ai_messages = build(&context(stored), "Flag insults.", Strictness::Normal, None, None, None);
+            assert_eq!(ai_messages.len(), stored + 1);
+            let note = sparse_context_note(stored - 1);
+            assert!(newest_content(&ai_messages).contains(&note), "no note with {stored} stored messages");
+        }
+    }
+
+    #[test]
+    fn enough_context_is_not_noted() {
+        let min_context = config::init_for_tests().min_context;
+        let ai_messages = build(&context(min_context), "Flag insults.", Strictness::Normal, None, None, None);
+        assert!(!newest_content(&ai_messages).contains("NOTE:"));
+    }
+
+    #[test]
+    fn sparse_context_note_counts_earlier_messages() {
+        assert!(sparse_context_note(0).contains("no earlier messages"));
+        assert!(sparse_context_note(1).contains("only 1 earlier message from"));
+        assert!(sparse_context_note(3).contains("only 3 earlier messages"));
+    }
+
+    fn user(content: &str) -> AIMessage {
+        AIMessage::new(Some(content.into()), "user".into())
+    }
+
+    fn contents(ai_messages: &[AIMessage]) -> Vec<(&str, &str)> {
+        ai_messages.iter().map(|x| (x.role.as_str(), x.content.as_deref().unwrap_or_default())).collect()
+    }
+
+    #[test]
+    fn separate_packing_keeps_one_message_each() {
+        let packed = pack(vec![user("summary"), user("a"), user("b")], 2, ContextPacking::Separate, "---");
+        assert_eq!(contents(&packed), [("user", "summary"), ("user", "a"), ("user", "b")]);
+    }
+
+    #[test]
+    fn single_packing_tags_and_separates_chat_messages() {
+        let packed = pack(vec![user("a"), user("b")], 2, ContextPacking::Single, "---");
+        assert_eq!(contents(&packed), [("user", "[MESSAGE 1]\na\n---\n[MESSAGE 2]\nb")]);
+    }
+
+    #[test]
+    fn single_packing_leads_with_the_untagged_summary() {
+        let packed = pack(vec![user("summary"), user("a")], 1, ContextPacking::Single, "=====");
+        assert_eq!(contents(&packed), [("user", "summary\n=====\n[MESSAGE 1]\na")]);
+    }
+
+    #[test]
+    fn single_packing_of_nothing_adds_no_message() {
+        assert!(pack(Vec::new(), 0, ContextPacking::Single, "---").is_empty());
+    }
+
+    fn payload(ai_messages: Vec<AIMessage>) -> serde_json::Value {
+        serde_json::to_value(ai_messages).unwrap()
+    }
+
+    #[test]
+    fn instructions_lead_in_the_system_role_by_default() {
+        let placed = place_instructions("rules".into(), vec![user("hi")], "system", false);
+        assert_eq!(payload(placed), serde_json::json!([
+            { "content": "rules", "role": "system" },
+            { "content": "hi", "role": "user" }
+        ]));
+    }
+
+    #[test]
+    fn instructions_lead_in_a_custom_role() {
+        let placed = place_instructions("rules".into(), vec![user("hi")], "developer", false);
+        assert_eq!(payload(placed), serde_json::json!([
+            { "content": "rules", "role": "developer" },
+            { "content": "hi", "role": "user" }
+        ]));
+    }
+
+    #[test]
+    fn folded_instructions_top_the_first_user_message() {
+        let placed = place_instructions("rules".into(), vec![user("hi"), user("there")], "developer", true);
+        assert_eq!(payload(placed), serde_json::json!([
+            { "content": "rules\n\nhi", "role": "user" },
+            { "content": "there", "role": "user" }
+        ]));
+    }
+
+    #[test]
+    fn folding_skips_messages_that_are_not_from_the_user() {
+        let messages = vec![AIMessage::new(Some("earlier answer".into()), "assistant".into()), user("hi")];
+        assert_eq!(payload(place_instructions("rules".into(), messages, "system", true)), serde_json::json!([
+            { "content": "earlier answer", "role": "assistant" },
+            { "content": "rules\n\nhi", "role": "user" }
+        ]));
+    }
+
+    #[test]
+    fn folding_without_a_user_message_adds_one() {
+        let messages = vec![AIMessage::new(Some("earlier answer".into()), "assistant".into())];
+        assert_eq!(payload(place_instructions("rules".into(), messages, "system", true)), serde_json::json!([
+            { "content": "rules", "role": "user" },
+            { "content": "earlier answer", "role": "assistant" }
+        ]));
+    }
+}
@@ -0,0 +1,150 @@
+pub mod fireworks;
+pub mod ollama;
+
+use std::{fmt, time::{Duration, SystemTime}};
+
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use tracing::warn;
+use crate::{config::{Config, ProviderKind, Sampling}, metrics, AIMessage};
+
+/// Choices longer than this are not parsed; a verdict is a small object, so anything this big is garbage.
+const MAX_RESPONSE_LEN: usize = 16 * 1024;
+
+#[derive(Debug)]
+pub enum ProviderError {
+    Request(reqwest::Error),
+    Status(u16, String),
+    /// A 429, with how long the provider asked to wait if it sent `Retry-After`.
+    RateLimited(Option<Duration>, String),
+    NoChoices,
+    EmptyResponse,
+    /// `DAILY_REQUEST_BUDGET` or `DAILY_TOKEN_BUDGET` is used up, so nothing was sent.
+    OverBudget
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request(why) => write!(f, "request failed: {why}"),
+            Self::Status(status, body) => write!(f, "provider returned {status}: {body}"),
+            Self::RateLimited(_, body) => write!(f, "provider rate limited the request: {body}"),
+            Self::NoChoices => write!(f, "provider returned no choices"),
+            Self::EmptyResponse => write!(f, "provider returned no message content"),
+            Self::OverBudget => write!(f, "the daily AI budget is used up")
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(why: reqwest::Error) -> Self {
+        Self::Request(why)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+#[derive(Serialize)]
+pub struct Completion {
+    pub content: String,
+    /// The choice stopped at `MAX_TOKENS`, so its JSON is most likely cut off.
+    pub truncated: bool,
+}
+
+impl Completion {
+    /// The content, unless it was cut off at `MAX_TOKENS` or is too large to be a verdict.
+    pub fn parseable(&self) -> Option<&str> {
+        if self.truncated {
+            warn!("ignoring a response that hit MAX_TOKENS, consider raising it");
+        }else if self.content.len() > MAX_RESPONSE_LEN {
+            warn!("ignoring a {} byte response", self.content.len());
+        }else{
+            return Some(&self.content);
+        }
+        metrics::increment(&metrics::get().truncated_responses);
+        None
+    }
+}
+
+pub struct ChatResponse {
+    /// Every choice, in the order the provider indexed them. Never empty.
+    pub choices: Vec<Completion>,
+    pub usage: Option<Usage>,
+}
+
+/// A chat-completion backend that answers with the assistant's JSON verdict.
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Replaces the API key used from the next request on. Returns false for providers without one.
+    fn set_api_key(&self, _api_key: String) -> bool {
+        false
+    }
+    /// A response without choices is reported as [`ProviderError::NoChoices`].
+    async fn chat(&self, client: &reqwest::Client, model: &str, messages: Vec<AIMessage>, sampling: Sampling) -> Result<ChatResponse, ProviderError>;
+}
+
+pub fn from_config(config: &Config) -> Box<dyn ChatProvider> {
+    build(config.provider, config.fireworks_api_key.clone(), config.ollama_url.clone())
+}
+
+/// The `FALLBACK_PROVIDER` with its model, reusing the primary's key and URL unless they are overridden.
+pub fn fallback_from_config(config: &Config) -> Option<(Box<dyn ChatProvider>, String)> {
+    let kind = config.fallback_provider?;
+    let fireworks_api_key = config.fallback_fireworks_api_key.clone().or_else(|| config.fireworks_api_key.clone());
+    let ollama_url = config.fallback_ollama_url.clone().unwrap_or_else(|| config.ollama_url.clone());
+    let model = config.fallback_model.clone().unwrap_or_else(|| config.model.clone());
+    Some((build(kind, fireworks_api_key, ollama_url), model))
+}
+
+fn build(kind: ProviderKind, fireworks_api_key: Option<String>, ollama_url: String) -> Box<dyn ChatProvider> {
+    match kind {
+        ProviderKind::Fireworks => Box::new(fireworks::FireworksProvider::new(
+            fireworks_api_key.unwrap_or_default()
+        )),
+        ProviderKind::Ollama => Box::new(ollama::OllamaProvider::new(ollama_url))
+    }
+}
+
+/// The HTTP client shared by providers and heuristics, with pooling tuned from config.
+pub fn http_client(config: &Config) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder()
+        .pool_max_idle_per_host(config.http_pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(config.http_pool_idle_timeout_secs))
+        .tcp_keepalive(Some(Duration::from_secs(config.http_tcp_keepalive_secs)).filter(|x| !x.is_zero()));
+    if config.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder.build().expect("the HTTP client configuration is valid")
+}
+
+async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response, ProviderError> {
+    let status = response.status().as_u16();
+    if status == 429 {
+        let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)
+            .and_then(|x| x.to_str().ok())
+            .and_then(retry_after);
+        let body = response.text().await.unwrap_or_default();
+        return Err(ProviderError::RateLimited(retry_after, body));
+    }
+    if status != 200 {
+        let body = response.text().await.unwrap_or_default();
+        return Err(ProviderError::Status(status, body));
+    }
+    Ok(response)
+}
+
+/// Parses a `Retry-After` value, which is either a number of seconds or an HTTP-date.
+fn retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    // A date already in the past means the request can be retried right away.
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
@@ -0,0 +1,129 @@
+use std::sync::RwLock;
+
+use serenity::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::{config::Sampling, AIMessage};
+use super::{error_for_status, ChatProvider, ChatResponse, Completion, ProviderError, Usage};
+
+const COMPLETIONS_URL: &str = "https://api.fireworks.ai/inference/v1/chat/completions";
+
+#[derive(Deserialize, Serialize)]
+struct ResponseFormat {
+    r#type: String
+}
+
+impl Default for ResponseFormat {
+    fn default() -> Self {
+        Self {
+            r#type: "json_object".into()
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct FireworksPayload {
+    model: String,
+    messages: Vec<AIMessage>,
+    response_format: Option<ResponseFormat>,
+    max_tokens: u64,
+    top_p: u8,
+    top_k: u8,
+    presence_penalty: u8,
+    frequency_penalty: u8,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+impl FireworksPayload {
+    fn new(model: String, messages: Vec<AIMessage>, sampling: Sampling) -> Self {
+        Self {
+            model,
+            messages,
+            response_format: Some(ResponseFormat::default()),
+            max_tokens: sampling.max_tokens,
+            top_p: sampling.top_p,
+            top_k: sampling.top_k,
+            presence_penalty: sampling.presence_penalty,
+            frequency_penalty: sampling.frequency_penalty,
+            temperature: sampling.temperature,
+            seed: sampling.seed
+        }
+    }
+}
+
+/// The request body sent for `messages`, for `/dump-payload`.
+pub fn payload(model: &str, messages: Vec<AIMessage>, sampling: Sampling) -> serde_json::Value {
+    serde_json::to_value(FireworksPayload::new(model.into(), messages, sampling)).unwrap()
+}
+
+#[derive(Deserialize, Serialize)]
+struct Choice {
+    index: usize,
+    message: AIMessage,
+    #[serde(default)]
+    finish_reason: Option<String>
+}
+
+#[derive(Deserialize, Serialize)]
+struct AIResponse {
+    choices: Vec<Choice>,
+    created: i32,
+    id: String,
+    model: String,
+    object: String,
+    usage: Option<Usage>,
+}
+
+pub struct FireworksProvider {
+    /// Swapped at runtime with `/rotatekey`.
+    api_key: RwLock<String>
+}
+
+impl FireworksProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            api_key: RwLock::new(api_key)
+        }
+    }
+}
+
+#[async_trait]
+impl ChatProvider for FireworksProvider {
+    fn name(&self) -> &'static str {
+        "fireworks"
+    }
+
+    fn set_api_key(&self, api_key: String) -> bool {
+        *self.api_key.write().unwrap() = api_key;
+        true
+    }
+
+    async fn chat(&self, client: &reqwest::Client, model: &str, messages: Vec<AIMessage>, sampling: Sampling) -> Result<ChatResponse, ProviderError> {
+        let payload = FireworksPayload::new(model.into(), messages, sampling);
+        let api_key = self.api_key.read().unwrap().clone();
+        let response = client.post(COMPLETIONS_URL)
+            .bearer_auth(api_key)
+            .json(&payload)
+            .send()
+            .await?;
+        let mut body : AIResponse = error_for_status(response).await?.json().await?;
+        if body.choices.is_empty() {
+            return Err(ProviderError::NoChoices);
+        }
+        body.choices.sort_by_key(|choice| choice.index);
+        let choices = body.choices.into_iter()
+            .filter_map(|choice| Some(Completion {
+                content: choice.message.content?,
+                truncated: choice.finish_reason.as_deref() == Some("length")
+            }))
+            .collect::<Vec<Completion>>();
+        if choices.is_empty() {
+            return Err(ProviderError::EmptyResponse);
+        }
+        Ok(ChatResponse {
+            choices,
+            usage: body.usage
+        })
+    }
+}
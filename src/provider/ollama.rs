@@ -0,0 +1,98 @@
+use serenity::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::{config::Sampling, AIMessage};
+use super::{error_for_status, ChatProvider, ChatResponse, Completion, ProviderError, Usage};
+
+#[derive(Serialize)]
+struct OllamaOptions {
+    num_predict: u64,
+    temperature: f32,
+    top_k: u8,
+    top_p: u8,
+    presence_penalty: u8,
+    frequency_penalty: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+impl From<Sampling> for OllamaOptions {
+    fn from(sampling: Sampling) -> Self {
+        Self {
+            num_predict: sampling.max_tokens,
+            temperature: sampling.temperature,
+            top_k: sampling.top_k,
+            top_p: sampling.top_p,
+            presence_penalty: sampling.presence_penalty,
+            frequency_penalty: sampling.frequency_penalty,
+            seed: sampling.seed
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OllamaPayload {
+    model: String,
+    messages: Vec<AIMessage>,
+    /// Ollama's JSON mode; the equivalent of `response_format: json_object`.
+    format: String,
+    stream: bool,
+    options: OllamaOptions,
+}
+
+/// `/api/chat` returns a single message instead of a list of choices.
+#[derive(Deserialize)]
+struct OllamaResponse {
+    message: AIMessage,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+}
+
+pub struct OllamaProvider {
+    url: String
+}
+
+impl OllamaProvider {
+    pub fn new(url: String) -> Self {
+        Self {
+            url
+        }
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    async fn chat(&self, client: &reqwest::Client, model: &str, messages: Vec<AIMessage>, sampling: Sampling) -> Result<ChatResponse, ProviderError> {
+        let payload = OllamaPayload {
+            model: model.into(),
+            messages,
+            format: "json".into(),
+            stream: false,
+            options: sampling.into()
+        };
+        let url = format!("{}/api/chat", self.url.trim_end_matches('/'));
+        let response = client.post(url)
+            .json(&payload)
+            .send()
+            .await?;
+        let body : OllamaResponse = error_for_status(response).await?.json().await?;
+        let content = body.message.content.ok_or(ProviderError::EmptyResponse)?;
+        Ok(ChatResponse {
+            choices: vec![Completion {
+                content,
+                truncated: body.done_reason.as_deref() == Some("length")
+            }],
+            usage: Some(Usage {
+                prompt_tokens: body.prompt_eval_count,
+                completion_tokens: body.eval_count
+            })
+        })
+    }
+}